@@ -0,0 +1,317 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    process::ExitCode,
+};
+
+use crate::{
+    machine::{
+        device_config::DeviceConfig,
+        devices::{null_device::NullDevice, stream_device::StreamDevice},
+    },
+    processor::{Processor, ProcessorExt, StopReason},
+    timing::CycleCosts,
+};
+
+// Headless batch runner
+//
+// sic_xe_simulator run <prog.obj>... [--load-at <addr>] [--max-steps N] [--devices <file>]
+//                     [--stdin <file>] [--stdout <file>] [--halt-at <loc>] [--halt-opcode <hex>]
+//                     [--restore <snapshot>] [--save <snapshot>] [--trace <file>]
+//                     [--profile <file>] [--cycles <file>]
+//
+// The object files are linked one after another, the first E record address is the entry.
+// --restore continues from a snapshot instead (the programs are optional then, and loaded
+// over it), --save writes one when the run stops. --trace writes an execution trace (see trace),
+// --profile the instruction profile (see profile) when the run stops. --cycles sets the cycle
+// costs (see timing).
+// Devices come from the --devices map (see device_config), except that device 0 reads --stdin
+// (default: stdin) and device 1 writes --stdout (default: stdout).
+// Runs at full speed until the program halts, faults or executes N instructions.
+// Halting: a jump to itself (`halt J halt`), going idle for good, or reaching --halt-at
+// or an instruction with --halt-opcode.
+
+/// exit status: program halted
+const EXIT_HALTED: u8 = 0;
+/// exit status: bad arguments, unreadable files or output that couldn't be written
+const EXIT_USAGE: u8 = 1;
+/// exit status: program check in supervisor mode
+const EXIT_FAULT: u8 = 2;
+/// exit status: --max-steps reached
+const EXIT_STEP_LIMIT: u8 = 3;
+
+const USAGE: &str = "usage: sic_xe_simulator run <prog.obj>... [--load-at <addr>] [--max-steps N] \
+                     [--devices <file>] [--stdin <file>] [--stdout <file>] [--halt-at <loc>] \
+                     [--halt-opcode <hex>] [--restore <snapshot>] [--save <snapshot>] \
+                     [--trace <file>] [--profile <file>] [--cycles <file>]";
+
+struct Options {
+    programs: Vec<String>,
+    /// 0x prefixed hex or decimal, None -> where it was assembled
+    load_at: Option<String>,
+    max_steps: Option<u64>,
+    devices: Option<String>,
+    stdin: Option<String>,
+    stdout: Option<String>,
+    /// label or address, resolved after loading
+    halt_at: Option<String>,
+    halt_opcode: Option<u8>,
+    restore: Option<String>,
+    save: Option<String>,
+    trace: Option<String>,
+    profile: Option<String>,
+    cycles: Option<String>,
+}
+
+impl Options {
+    /// args after "run"
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut programs = Vec::new();
+        let mut load_at = None;
+        let mut max_steps = None;
+        let mut devices = None;
+        let mut stdin = None;
+        let mut stdout = None;
+        let mut halt_at = None;
+        let mut halt_opcode = None;
+        let mut restore = None;
+        let mut save = None;
+        let mut trace = None;
+        let mut profile = None;
+        let mut cycles = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--max-steps" => {
+                    let value = value()?;
+                    let steps = value.parse().map_err(|_| format!("Invalid step count {value}"))?;
+                    max_steps = Some(steps);
+                }
+                "--load-at" => load_at = Some(value()?.clone()),
+                "--devices" => devices = Some(value()?.clone()),
+                "--stdin" => stdin = Some(value()?.clone()),
+                "--stdout" => stdout = Some(value()?.clone()),
+                "--halt-at" => halt_at = Some(value()?.clone()),
+                "--restore" => restore = Some(value()?.clone()),
+                "--save" => save = Some(value()?.clone()),
+                "--trace" => trace = Some(value()?.clone()),
+                "--profile" => profile = Some(value()?.clone()),
+                "--cycles" => cycles = Some(value()?.clone()),
+                "--halt-opcode" => {
+                    let value = value()?;
+                    let hex = value.strip_prefix("0x").unwrap_or(value);
+                    let opcode =
+                        u8::from_str_radix(hex, 16).map_err(|_| format!("Invalid opcode {value}"))?;
+                    halt_opcode = Some(opcode);
+                }
+                option if option.starts_with("--") => return Err(format!("Unknown option {arg}")),
+                _ => programs.push(arg.clone()),
+            }
+        }
+
+        if programs.is_empty() && restore.is_none() {
+            return Err("Missing program".to_string());
+        }
+        Ok(Self {
+            programs,
+            load_at,
+            max_steps,
+            devices,
+            stdin,
+            stdout,
+            halt_at,
+            halt_opcode,
+            restore,
+            save,
+            trace,
+            profile,
+            cycles,
+        })
+    }
+}
+
+/// Entry point of `sic_xe_simulator run ...`, args are the ones after "run".
+pub fn main(args: &[String]) -> ExitCode {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{error}");
+            eprintln!("{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    match run(&options) {
+        Ok(code) => ExitCode::from(code),
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<u8, String> {
+    let config = match &options.devices {
+        Some(file_name) => Some(DeviceConfig::read(file_name)?),
+        None => None,
+    };
+    let costs = match &options.cycles {
+        Some(file_name) => Some(CycleCosts::read(file_name)?),
+        None => None,
+    };
+    let mapped = |index| config.as_ref().is_some_and(|config| config.has_device(index));
+    // None -> keep the device from the config
+    let reader: Option<Box<dyn Read + Send>> = match &options.stdin {
+        Some(path) => Some(Box::new(
+            File::open(path).map_err(|error| format!("Could not open {path}: {error}"))?,
+        )),
+        None if mapped(0) => None,
+        None => Some(Box::new(io::stdin())),
+    };
+    let writer: Option<Box<dyn Write + Send>> = match &options.stdout {
+        Some(path) => Some(Box::new(
+            File::create(path).map_err(|error| format!("Could not create {path}: {error}"))?,
+        )),
+        None if mapped(1) => None,
+        None => Some(Box::new(io::stdout())),
+    };
+
+    let processor_ptr = Processor::new_handle();
+    if let Some(config) = &config {
+        config.apply(&mut processor_ptr.lock().unwrap().machine)?;
+    }
+    // device states go into the configured devices
+    if let Some(file_name) = &options.restore {
+        for problem in processor_ptr.restore_snapshot(file_name)? {
+            eprintln!("{problem}");
+        }
+    }
+    if !options.programs.is_empty() {
+        let load_at = match &options.load_at {
+            Some(text) => {
+                let address = processor_ptr.lock().unwrap().parse_location(text);
+                Some(address.ok_or_else(|| format!("Invalid address {text}"))?)
+            }
+            None => None,
+        };
+        let programs: Vec<&str> = options.programs.iter().map(String::as_str).collect();
+        processor_ptr.load_files(&programs, load_at)?;
+    }
+
+    let mut processor = processor_ptr.lock().unwrap();
+    processor.set_journaling(false);
+    if let Some(location) = &options.halt_at {
+        let address = processor
+            .parse_location(location)
+            .ok_or_else(|| format!("Unknown location {location}"))?;
+        processor.set_halt_address(Some(address));
+    }
+    processor.set_halt_opcode(options.halt_opcode);
+    if let Some(costs) = costs {
+        processor.set_costs(costs);
+    }
+    if let Some(file_name) = &options.trace {
+        processor.start_trace(file_name)?;
+    }
+    if options.profile.is_some() {
+        processor.start_profile();
+    }
+    if reader.is_some() {
+        processor.machine.set_device(0, Box::new(StreamDevice::new(reader, None)));
+    }
+    let write_error = writer.is_some().then(|| {
+        let device = StreamDevice::new(None, writer);
+        let error = device.get_error();
+        processor.machine.set_device(1, Box::new(device));
+        error
+    });
+
+    let reason = processor.run(options.max_steps);
+    // dropping the device flushes the output
+    processor.machine.set_device(1, Box::new(NullDevice {}));
+    if let Some(Err(error)) = processor.stop_trace() {
+        eprintln!("{error}");
+    }
+
+    if let (Some(file_name), Some(report)) = (&options.profile, processor.profile_report(None)) {
+        fs::write(file_name, report.join("\n") + "\n")
+            .map_err(|error| format!("Could not write {file_name}: {error}"))?;
+    }
+    if let Some(file_name) = &options.save {
+        processor.snapshot().write(file_name)?;
+    }
+
+    let pc = processor.machine.registers.get_pc();
+    let steps = processor.get_steps();
+    let cycles = processor.get_cycles();
+    let code = match reason {
+        StopReason::Halted => {
+            let pc = processor.get_halted().unwrap_or(pc);
+            eprintln!("halted at {pc:06X} after {steps} steps, {cycles} cycles");
+            EXIT_HALTED
+        }
+        StopReason::Fault(fault) => {
+            eprintln!("fault: {fault} after {steps} steps, {cycles} cycles");
+            EXIT_FAULT
+        }
+        StopReason::StepLimit => {
+            eprintln!("step limit reached at {pc:06X} after {steps} steps, {cycles} cycles");
+            EXIT_STEP_LIMIT
+        }
+    };
+    if let Some(error) = write_error.and_then(|error| error.lock().unwrap().take()) {
+        let name = options.stdout.as_deref().unwrap_or("stdout");
+        return Err(format!("Could not write {name}: {error}"));
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_file::TempFile;
+
+    /// LDA #0x41  WD 1  halt J halt
+    const PROGRAM: &str = "HPROG  000000000009\nT00000009010041DF00013F2FFD\nE000000\n";
+
+    fn args(args: &[&str]) -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() }
+
+    #[test]
+    fn parses_options() {
+        let options = Options::parse(&args(&["a.obj", "--max-steps", "10", "b.obj"])).unwrap();
+        assert_eq!(options.programs, ["a.obj", "b.obj"]);
+        assert_eq!(options.max_steps, Some(10));
+        let options = Options::parse(&args(&["--restore", "s.json", "--halt-opcode", "0x3C"]));
+        assert_eq!(options.unwrap().halt_opcode, Some(0x3C));
+
+        let error = |list: &[&str]| Options::parse(&args(list)).err().unwrap();
+        assert_eq!(error(&[]), "Missing program");
+        assert_eq!(error(&["a.obj", "--fast"]), "Unknown option --fast");
+        assert_eq!(error(&["a.obj", "--max-steps", "many"]), "Invalid step count many");
+        assert_eq!(error(&["a.obj", "--stdout"]), "--stdout needs a value");
+    }
+
+    #[test]
+    fn runs_until_halted_or_the_step_limit() {
+        let program_file = TempFile::with_contents("batch_prog.obj", PROGRAM.as_bytes());
+        let stdout_file = TempFile::new("batch_stdout.txt");
+        let (program, stdout) = (program_file.path(), stdout_file.path());
+
+        let options = Options::parse(&args(&[program, "--stdout", stdout])).unwrap();
+        assert_eq!(run(&options), Ok(EXIT_HALTED));
+        assert_eq!(fs::read_to_string(stdout).unwrap(), "A");
+
+        let options =
+            Options::parse(&args(&[program, "--stdout", stdout, "--max-steps", "1"])).unwrap();
+        assert_eq!(run(&options), Ok(EXIT_STEP_LIMIT));
+        assert_eq!(fs::read_to_string(stdout).unwrap(), "");
+
+        fs::write(program, "HPROG  000000000001\nT00000001FF\nE000000\n").unwrap();
+        let options = Options::parse(&args(&[program, "--stdout", stdout])).unwrap();
+        assert_eq!(run(&options), Ok(EXIT_FAULT));
+
+        let options = Options::parse(&args(&["missing.obj"])).unwrap();
+        assert!(run(&options).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+// Shadow call stack
+//
+// SIC/XE has no stack for calls: JSUB saves the return address in L and RSUB jumps to L.
+// The processor keeps its own stack of the calls, a JSUB pushes a frame with the return
+// address in L, an RSUB pops it. An RSUB that doesn't return to the address of the innermost
+// call (L was overwritten without being saved, or restored wrong) is a mismatch. It unwinds to
+// the call it returned from, if there is one, otherwise drops the innermost call.
+// An RSUB without any call (e.g. the end of the program) is ignored.
+
+#[derive(Debug, Clone)]
+pub struct CallFrame {
+    /// address of the JSUB
+    pub call_site: usize,
+    /// called routine
+    pub target: usize,
+    /// L after the JSUB
+    pub return_address: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReturnMismatch {
+    /// address of the RSUB
+    pub pc: usize,
+    /// where it returned to (L)
+    pub returned_to: usize,
+    /// return address of the innermost call
+    pub expected: usize,
+    /// calls dropped by the return
+    pub unwound: usize,
+}
+
+impl fmt::Display for ReturnMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RSUB at {:06X} returned to {:06X}, expected {:06X}",
+            self.pc, self.returned_to, self.expected
+        )?;
+        if self.unwound > 1 {
+            write!(f, " ({} calls unwound)", self.unwound)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    /// outermost first
+    frames: Vec<CallFrame>,
+    /// the last mismatched return, kept until the stack is cleared
+    mismatch: Option<ReturnMismatch>,
+}
+
+impl CallStack {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn get_frames(&self) -> &[CallFrame] { &self.frames }
+    pub fn get_mismatch(&self) -> Option<&ReturnMismatch> { self.mismatch.as_ref() }
+    pub fn clear(&mut self) { *self = Self::new(); }
+
+    pub fn call(&mut self, call_site: usize, target: usize, return_address: usize) {
+        self.frames.push(CallFrame { call_site, target, return_address });
+    }
+
+    /// RSUB at pc returned to returned_to
+    pub fn ret(&mut self, pc: usize, returned_to: usize) {
+        let Some(innermost) = self.frames.last() else { return };
+        if innermost.return_address == returned_to {
+            self.frames.pop();
+            return;
+        }
+        let expected = innermost.return_address;
+        let depth = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_address == returned_to)
+            .unwrap_or(self.frames.len() - 1);
+        let unwound = self.frames.len() - depth;
+        self.frames.truncate(depth);
+        self.mismatch = Some(ReturnMismatch { pc, returned_to, expected, unwound });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returns_pop_their_calls() {
+        let mut stack = CallStack::new();
+        stack.ret(0x10, 0x20);
+        stack.call(0x00, 0x100, 0x03);
+        stack.call(0x100, 0x200, 0x103);
+        assert_eq!(stack.get_frames().len(), 2);
+        stack.ret(0x200, 0x103);
+        stack.ret(0x106, 0x03);
+        assert!(stack.get_frames().is_empty());
+        assert!(stack.get_mismatch().is_none());
+    }
+
+    #[test]
+    fn mismatched_return_unwinds_to_the_call_it_returned_from() {
+        let mut stack = CallStack::new();
+        stack.call(0x00, 0x100, 0x03);
+        stack.call(0x100, 0x200, 0x103);
+        stack.call(0x200, 0x300, 0x203);
+        stack.ret(0x300, 0x03);
+        assert!(stack.get_frames().is_empty());
+        let mismatch = stack.get_mismatch().unwrap().to_string();
+        let expected = "RSUB at 000300 returned to 000003, expected 000203 (3 calls unwound)";
+        assert_eq!(mismatch, expected);
+
+        // nowhere known: only the innermost call is dropped
+        stack.call(0x00, 0x100, 0x03);
+        stack.call(0x100, 0x200, 0x103);
+        stack.ret(0x200, 0x999);
+        assert_eq!(stack.get_frames().len(), 1);
+        assert_eq!(stack.get_mismatch().unwrap().unwound, 1);
+    }
+}
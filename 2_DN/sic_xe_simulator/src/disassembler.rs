@@ -0,0 +1,402 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    process::ExitCode,
+};
+
+use crate::{
+    loader::{self, ObjectProgram},
+    machine::{opcodes::Opcode, Machine},
+    sic_xe::{
+        get_format_sic_f3_f4_bits, get_r1_r2, is_base_relative, is_format_f4, is_format_sic,
+        is_immediate, is_indirect, is_pc_relative, is_x, u8arr_to_i24, MASK_WORD,
+    },
+};
+
+// Disassembler
+//
+// Operands are written the way the assembler reads them, numbers in decimal:
+//   F2      RMO S,A   CLEAR X   SHIFTL A,4   SVC 2
+//   F3/F4   label   #10   #label   @label   label,X   +JSUB label
+// A target without a label is written as its address. Base relative targets use the current
+// B register.
+//
+// sic_xe_simulator disasm <prog.obj>
+// prints the program as source: labels for the section name, its D records and every target
+// inside it, BYTE for bytes that aren't instructions, RESB for runs of zeros.
+
+/// register names by number, as in F2 instructions
+const REGISTERS: [&str; 10] = ["A", "X", "L", "B", "S", "T", "F", "", "PC", "SW"];
+
+/// this many zero bytes in a row are data (RESB), not LDA 0 instructions
+const ZERO_RUN: usize = 6;
+
+/// Decoded instruction, or a data byte
+pub struct Instruction {
+    /// in bytes
+    pub len: usize,
+    /// None -> data byte
+    pub opcode: Option<Opcode>,
+    /// + in front for format 4, BYTE for data
+    pub mnemonic: String,
+    pub operand: String,
+    /// address the operand names, before indirection and indexing
+    pub target: Option<usize>,
+    /// address accessed with the current X, B and memory, None -> no memory operand
+    pub effective: Option<usize>,
+}
+
+fn register(number: u8) -> String {
+    match REGISTERS.get(number as usize) {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => number.to_string(),
+    }
+}
+
+/// sign extend the lowest bits
+fn signed(value: usize, bits: u32) -> i64 {
+    let value = value as i64;
+    if value & (1 << (bits - 1)) != 0 { value - (1 << bits) } else { value }
+}
+
+/// Decode the instruction at address.
+/// symbol_at: how to write a target address, None -> in decimal
+pub fn decode(
+    machine: &Machine,
+    address: usize,
+    symbol_at: &dyn Fn(usize) -> Option<String>,
+) -> Instruction {
+    let memory = &machine.memory;
+    // bytes past the end of memory read as 0
+    let get_byte = |address: usize| {
+        if memory.is_valid(address, 1) { memory.get_byte(address) } else { 0 }
+    };
+    let name = |address: usize| symbol_at(address).unwrap_or_else(|| address.to_string());
+
+    let b1 = get_byte(address);
+    let instruction = |len: usize, opcode: Opcode, mnemonic: String, operand: String| Instruction {
+        len,
+        opcode: Some(opcode),
+        mnemonic,
+        operand,
+        target: None,
+        effective: None,
+    };
+    let data = Instruction {
+        len: 1,
+        opcode: None,
+        mnemonic: "BYTE".to_string(),
+        operand: b1.to_string(),
+        target: None,
+        effective: None,
+    };
+    let Some(opcode) = Opcode::from_byte(b1 & 0xFC) else { return data };
+    let mnemonic = format!("{:?}", opcode).to_uppercase();
+
+    // format 1 and 2 opcodes have no n and i bits
+    if opcode.is_format_1() {
+        if b1 != opcode as u8 {
+            return data;
+        }
+        return instruction(1, opcode, mnemonic, String::new());
+    }
+    let b2 = get_byte(address + 1);
+    if opcode.is_format_2() {
+        if b1 != opcode as u8 {
+            return data;
+        }
+        let (r1, r2) = get_r1_r2(&b2);
+        let operand = match opcode {
+            Opcode::Clear | Opcode::Tixr => register(r1),
+            Opcode::Svc => r1.to_string(),
+            Opcode::Shiftl | Opcode::Shiftr => format!("{},{}", register(r1), r2 + 1),
+            _ => format!("{},{}", register(r1), register(r2)),
+        };
+        return instruction(2, opcode, mnemonic, operand);
+    }
+
+    // SIC / F3 / F4
+    let bits = get_format_sic_f3_f4_bits(&b1, &b2);
+    let b3 = get_byte(address + 2) as usize;
+    let sic = is_format_sic(&bits);
+    // b and p both set
+    if !sic && b2 & 0x60 == 0x60 {
+        return data;
+    }
+    let (len, disp, mnemonic) = if sic {
+        (3, ((b2 & 0x7F) as usize) << 8 | b3, mnemonic)
+    } else if is_format_f4(&bits) {
+        let disp = ((b2 & 0x0F) as usize) << 16 | b3 << 8 | get_byte(address + 3) as usize;
+        (4, disp, format!("+{mnemonic}"))
+    } else {
+        (3, ((b2 & 0x0F) as usize) << 8 | b3, mnemonic)
+    };
+    if matches!(opcode, Opcode::Rsub) {
+        return instruction(len, opcode, mnemonic, String::new());
+    }
+
+    let relative = !sic && (is_pc_relative(&bits) || is_base_relative(&bits));
+    let target = if sic {
+        disp
+    } else if is_pc_relative(&bits) {
+        let disp = signed(disp, if len == 3 { 12 } else { 20 });
+        (address as i64 + len as i64 + disp) as usize & MASK_WORD as usize
+    } else if is_base_relative(&bits) {
+        (machine.registers.get_b() & MASK_WORD) as usize + disp
+    } else {
+        disp
+    };
+
+    if !sic && is_immediate(&bits) {
+        // a relative immediate is an address, e.g. #label
+        let (mut operand, target) = if relative {
+            (format!("#{}", name(target)), Some(target))
+        } else {
+            (format!("#{disp}"), None)
+        };
+        if is_x(&bits) {
+            operand.push_str(",X");
+        }
+        return Instruction { target, ..instruction(len, opcode, mnemonic, operand) };
+    }
+
+    let indirect = !sic && is_indirect(&bits);
+    let mut operand = name(target);
+    let mut effective = target;
+    if indirect {
+        operand.insert(0, '@');
+        effective = if memory.is_valid(target, 3) {
+            u8arr_to_i24(memory.get_word(target)) as usize & MASK_WORD as usize
+        } else {
+            target
+        };
+    }
+    if is_x(&bits) {
+        operand.push_str(",X");
+        effective = effective.wrapping_add((machine.registers.get_x() & MASK_WORD) as usize);
+    }
+    Instruction {
+        target: Some(target),
+        effective: Some(effective),
+        ..instruction(len, opcode, mnemonic, operand)
+    }
+}
+
+/// Source of the bytes in start..end, assembled as program name.
+/// symbol_at: labels to keep, targets inside the range without one get L<hex address>
+/// entry: operand of END, None -> start
+pub fn to_source(
+    machine: &Machine,
+    name: &str,
+    start: usize,
+    end: usize,
+    entry: Option<usize>,
+    symbol_at: &dyn Fn(usize) -> Option<String>,
+) -> String {
+    let memory = &machine.memory;
+    let end = (start..end).find(|address| !memory.is_valid(*address, 1)).unwrap_or(end);
+
+    // pass 1: split into instructions, data and runs of zeros, collect the targets
+    enum Item {
+        Code,
+        Byte,
+        Word,
+        Zeros(usize),
+    }
+    let mut items: Vec<(usize, Item)> = Vec::new();
+    let mut targets = BTreeSet::new();
+    let mut address = start;
+    while address < end {
+        let zeros = (address..end).take_while(|address| memory.get_byte(*address) == 0).count();
+        let instruction = decode(machine, address, &|_| None);
+        let item = if zeros >= ZERO_RUN {
+            Item::Zeros(zeros)
+        } else if instruction.opcode.is_none()
+            || address + instruction.len > end
+            || (instruction.operand.starts_with(['#', '@']) && instruction.operand.ends_with(",X"))
+        {
+            // not an instruction, or one that can't be written: indexing with # or @
+            Item::Byte
+        } else if instruction.len == 3 && memory.get_byte(address) & 0x03 == 0 {
+            // SIC format, the assembler doesn't write it, so it's a word
+            Item::Word
+        } else {
+            targets.extend(instruction.target);
+            Item::Code
+        };
+        let len = match item {
+            Item::Code => instruction.len,
+            Item::Byte => 1,
+            Item::Word => 3,
+            Item::Zeros(len) => len,
+        };
+        items.push((address, item));
+        address += len;
+    }
+    // a target inside a run of zeros splits it
+    let items: Vec<(usize, Item)> = items
+        .into_iter()
+        .flat_map(|(address, item)| match item {
+            Item::Zeros(len) => {
+                let splits = targets.range(address + 1..address + len).copied();
+                let bounds: Vec<usize> =
+                    std::iter::once(address).chain(splits).chain([address + len]).collect();
+                bounds.windows(2).map(|run| (run[0], Item::Zeros(run[1] - run[0]))).collect()
+            }
+            item => vec![(address, item)],
+        })
+        .collect();
+
+    // labels: given symbols and targets, where an item starts
+    let mut labels: BTreeMap<usize, String> = BTreeMap::new();
+    for (address, _) in items.iter().filter(|(address, _)| *address != start) {
+        let label = symbol_at(*address)
+            .or_else(|| targets.contains(address).then(|| format!("L{address:05X}")));
+        labels.extend(label.map(|label| (*address, label)));
+    }
+    let label_at = |address: usize| {
+        if address == start { Some(name.to_string()) } else { labels.get(&address).cloned() }
+    };
+
+    // pass 2: one line per item
+    let line = |label: &str, mnemonic: &str, operand: &str| {
+        format!("{label:8}{mnemonic:8}{operand}").trim_end().to_string()
+    };
+    let mut lines = vec![line(name, "START", &start.to_string())];
+    for (address, item) in &items {
+        let (mnemonic, operand) = match item {
+            Item::Code => {
+                let instruction = decode(machine, *address, &label_at);
+                (instruction.mnemonic, instruction.operand)
+            }
+            Item::Byte => ("BYTE".to_string(), memory.get_byte(*address).to_string()),
+            Item::Word => {
+                let word = u8arr_to_i24(memory.get_word(*address)) & MASK_WORD;
+                ("WORD".to_string(), word.to_string())
+            }
+            Item::Zeros(len) => ("RESB".to_string(), len.to_string()),
+        };
+        let label = labels.get(address).map_or("", String::as_str);
+        lines.push(line(label, &mnemonic, &operand));
+    }
+    let entry = entry.unwrap_or(start);
+    let entry = label_at(entry).unwrap_or_else(|| entry.to_string());
+    lines.push(line("", "END", &entry));
+    lines.join("\n") + "\n"
+}
+
+/// Source of every control section of an .obj file, loaded where it was assembled.
+pub fn obj_to_source(file_name: &str) -> Result<String, String> {
+    let programs = ObjectProgram::read(file_name)?;
+    let mut machine = Machine::new();
+    let linked = loader::link(&mut machine, &programs, None)?;
+    let mut symbols: BTreeMap<usize, String> = BTreeMap::new();
+    for (name, address) in &linked.symbols {
+        // first name in alphabetical order, like Processor::symbol_at
+        let symbol = symbols.entry(*address).or_insert_with(|| name.clone());
+        if name < symbol {
+            *symbol = name.clone();
+        }
+    }
+    let sources: Vec<String> = programs
+        .iter()
+        .map(|program| {
+            let end = program.start + program.length;
+            let symbol_at = |address: usize| {
+                symbols.get(&address).filter(|_| (program.start..end).contains(&address)).cloned()
+            };
+            to_source(&machine, &program.name, program.start, end, program.entry, &symbol_at)
+        })
+        .collect();
+    Ok(sources.join("\n"))
+}
+
+/// Entry point of `sic_xe_simulator disasm <prog.obj>`, args are the ones after "disasm".
+pub fn main(args: &[String]) -> ExitCode {
+    let [file_name] = args else {
+        eprintln!("usage: sic_xe_simulator disasm <prog.obj>");
+        return ExitCode::FAILURE;
+    };
+    match obj_to_source(file_name) {
+        Ok(source) => {
+            print!("{source}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine_with(code: &[u8]) -> Machine {
+        let mut machine = Machine::new();
+        machine.memory.set_bytes(0, code);
+        machine
+    }
+
+    /// mnemonic and operand of the instruction at 0, targets in decimal
+    fn text(machine: &Machine) -> String {
+        let instruction = decode(machine, 0, &|_| None);
+        format!("{} {}", instruction.mnemonic, instruction.operand).trim_end().to_string()
+    }
+
+    #[test]
+    fn decodes_operands() {
+        let decoded = |code: &[u8]| text(&machine_with(code));
+        assert_eq!(decoded(&[0xAC, 0x04]), "RMO A,S");
+        assert_eq!(decoded(&[0xA4, 0x03]), "SHIFTL A,4");
+        assert_eq!(decoded(&[0xB0, 0x20]), "SVC 2");
+        assert_eq!(decoded(&[0x01, 0x00, 0x0A]), "LDA #10");
+        assert_eq!(decoded(&[0x01, 0x20, 0x0A]), "LDA #13");
+        assert_eq!(decoded(&[0x4B, 0x10, 0x00, 0x11]), "+JSUB 17");
+        assert_eq!(decoded(&[0x0E, 0x2F, 0xFD]), "STA @0");
+        assert_eq!(decoded(&[0x53, 0xA0, 0x0A]), "LDCH 13,X");
+        assert_eq!(decoded(&[0x00, 0x10, 0x00]), "LDA 4096");
+        assert_eq!(decoded(&[0x4F, 0x00, 0x00]), "RSUB");
+        // b and p both set, and a format 1 opcode with n/i bits
+        assert_eq!(decoded(&[0x03, 0x60, 0x00]), "BYTE 3");
+        assert_eq!(decoded(&[0xC5]), "BYTE 197");
+    }
+
+    #[test]
+    fn effective_address_uses_x_b_and_indirection() {
+        // LDA 0x10,X (base relative) and LDA @0x20
+        let mut machine = machine_with(&[0x03, 0xC0, 0x10, 0x02, 0x00, 0x20]);
+        machine.registers.set_b(0x100);
+        machine.registers.set_x(2);
+        machine.memory.set_word(0x20, [0x00, 0x03, 0x00]);
+        let instruction = decode(&machine, 0, &|_| None);
+        assert_eq!((instruction.target, instruction.effective), (Some(0x110), Some(0x112)));
+        let instruction = decode(&machine, 3, &|_| None);
+        assert_eq!((instruction.target, instruction.effective), (Some(0x20), Some(0x300)));
+    }
+
+    /// labels for the targets, WORD for data and RESB for the run of zeros
+    #[test]
+    fn renders_range_as_source() {
+        let code = [
+            0x01, 0x00, 0x0A, 0x4B, 0x10, 0x00, 0x11, 0x0E, 0x20, 0x0A, 0x53, 0xA0, 0x0A, 0xB4,
+            0x10, 0xAC, 0x04, 0x4F, 0x00, 0x00, 0x00, 0x00, 0x17, 0, 0, 0, 0, 0, 0,
+        ];
+        let machine = machine_with(&code);
+        let source = to_source(&machine, "PROG", 0, code.len(), None, &|_| None);
+        let expected = [
+            "PROG    START   0",
+            "        LDA     #10",
+            "        +JSUB   L00011",
+            "        STA     @L00014",
+            "        LDCH    L00017,X",
+            "        CLEAR   X",
+            "        RMO     A,S",
+            "L00011  RSUB",
+            "L00014  WORD    23",
+            "L00017  RESB    6",
+            "        END     PROG",
+        ];
+        assert_eq!(source.lines().collect::<Vec<_>>(), expected);
+    }
+}
@@ -0,0 +1,387 @@
+use std::fmt;
+
+use crate::{processor::Processor, sic_xe::u8arr_to_i24};
+
+// Debugger expressions, e.g. `X == 0x10 && [sp] > 3`
+//
+// or      := and ("||" and)*
+// and     := compare ("&&" compare)*
+// compare := bitor (("==" | "!=" | "<" | "<=" | ">" | ">=") bitor)?
+// bitor   := bitand ("|" bitand)*
+// bitand  := sum ("&" sum)*
+// sum     := product (("+" | "-") product)*
+// product := unary (("*" | "/" | "%") unary)*
+// unary   := ("-" | "!") unary | primary
+// primary := number | register | label | "[" or "]" | "byte" "[" or "]" | "(" or ")"
+//
+// number:   decimal or 0x prefixed hex
+// register: A, X, L, B, S, T, PC, SW (uppercase, so they don't shadow lowercase labels)
+// [e]:      word at address e, byte[e]: byte at address e
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    A,
+    X,
+    L,
+    B,
+    S,
+    T,
+    Pc,
+    Sw,
+}
+
+impl Register {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "A" => Register::A,
+            "X" => Register::X,
+            "L" => Register::L,
+            "B" => Register::B,
+            "S" => Register::S,
+            "T" => Register::T,
+            "PC" => Register::Pc,
+            "SW" => Register::Sw,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitOr,
+    BitAnd,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(i64),
+    Register(Register),
+    Label(String),
+    Word(Box<Node>),
+    Byte(Box<Node>),
+    Neg(Box<Node>),
+    Not(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Punct(&'static str),
+}
+
+/// longest first, so "<=" isn't read as "<"
+const PUNCTS: [&str; 20] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "&", "+", "-", "*", "/", "%", "!", "[", "]",
+    "(", ")",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let number = match literal.strip_prefix("0x").or_else(|| literal.strip_prefix("0X")) {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => literal.parse::<i64>(),
+            };
+            tokens.push(Token::Number(number.map_err(|_| format!("Invalid number {literal}"))?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let punct = PUNCTS
+                .iter()
+                .find(|punct| rest.starts_with(**punct))
+                .ok_or_else(|| format!("Unexpected '{c}'"))?;
+            tokens.push(Token::Punct(punct));
+            i += punct.len();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> { self.tokens.get(self.position) }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+    /// consume the first of puncts that is next
+    fn eat(&mut self, puncts: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Punct(punct)) if puncts.contains(punct) => {
+                let punct = *punct;
+                self.position += 1;
+                Some(punct)
+            }
+            _ => None,
+        }
+    }
+    fn expect(&mut self, punct: &'static str) -> Result<(), String> {
+        self.eat(&[punct]).map(|_| ()).ok_or_else(|| format!("Expected '{punct}'"))
+    }
+
+    /// left associative chain of binary operators
+    fn binary(
+        &mut self,
+        puncts: &[&'static str],
+        operand: fn(&mut Parser) -> Result<Node, String>,
+    ) -> Result<Node, String> {
+        let mut left = operand(self)?;
+        while let Some(punct) = self.eat(puncts) {
+            let right = operand(self)?;
+            left = Node::Binary(operator(punct), Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Node, String> { self.binary(&["||"], Parser::and) }
+    fn and(&mut self) -> Result<Node, String> { self.binary(&["&&"], Parser::compare) }
+    fn compare(&mut self) -> Result<Node, String> {
+        let left = self.bitor()?;
+        match self.eat(&["==", "!=", "<=", ">=", "<", ">"]) {
+            Some(punct) => {
+                let right = self.bitor()?;
+                Ok(Node::Binary(operator(punct), Box::new(left), Box::new(right)))
+            }
+            None => Ok(left),
+        }
+    }
+    fn bitor(&mut self) -> Result<Node, String> { self.binary(&["|"], Parser::bitand) }
+    fn bitand(&mut self) -> Result<Node, String> { self.binary(&["&"], Parser::sum) }
+    fn sum(&mut self) -> Result<Node, String> { self.binary(&["+", "-"], Parser::product) }
+    fn product(&mut self) -> Result<Node, String> {
+        self.binary(&["*", "/", "%"], Parser::unary)
+    }
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.eat(&["-", "!"]) {
+            Some("-") => Ok(Node::Neg(Box::new(self.unary()?))),
+            Some(_) => Ok(Node::Not(Box::new(self.unary()?))),
+            None => self.primary(),
+        }
+    }
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Node::Number(number)),
+            Some(Token::Ident(name)) if name == "byte" && self.eat(&["["]).is_some() => {
+                let address = self.or()?;
+                self.expect("]")?;
+                Ok(Node::Byte(Box::new(address)))
+            }
+            Some(Token::Ident(name)) => match Register::parse(&name) {
+                Some(register) => Ok(Node::Register(register)),
+                None => Ok(Node::Label(name)),
+            },
+            Some(Token::Punct("[")) => {
+                let address = self.or()?;
+                self.expect("]")?;
+                Ok(Node::Word(Box::new(address)))
+            }
+            Some(Token::Punct("(")) => {
+                let node = self.or()?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Some(Token::Punct(punct)) => Err(format!("Unexpected '{punct}'")),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn operator(punct: &str) -> Operator {
+    match punct {
+        "||" => Operator::Or,
+        "&&" => Operator::And,
+        "==" => Operator::Equal,
+        "!=" => Operator::NotEqual,
+        "<" => Operator::Less,
+        "<=" => Operator::LessEqual,
+        ">" => Operator::Greater,
+        ">=" => Operator::GreaterEqual,
+        "|" => Operator::BitOr,
+        "&" => Operator::BitAnd,
+        "+" => Operator::Add,
+        "-" => Operator::Sub,
+        "*" => Operator::Mul,
+        "/" => Operator::Div,
+        "%" => Operator::Rem,
+        _ => unreachable!("not a binary operator: {punct}"),
+    }
+}
+
+/// Parsed debugger expression, labels are resolved when evaluated.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+        let root = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {token:?}"));
+        }
+        Ok(Self { source: text.trim().to_string(), root })
+    }
+
+    pub fn evaluate(&self, processor: &Processor) -> Result<i64, String> {
+        evaluate(&self.root, processor)
+    }
+    /// nonzero -> true
+    pub fn is_true(&self, processor: &Processor) -> Result<bool, String> {
+        self.evaluate(processor).map(|val| val != 0)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.source) }
+}
+
+fn evaluate(node: &Node, processor: &Processor) -> Result<i64, String> {
+    let registers = &processor.machine.registers;
+    let memory = &processor.machine.memory;
+    let address = |node: &Node, len: usize| -> Result<usize, String> {
+        let address = evaluate(node, processor)?;
+        match usize::try_from(address) {
+            Ok(address) if memory.is_valid(address, len) => Ok(address),
+            _ => Err(format!("Address {address:X} out of range")),
+        }
+    };
+
+    Ok(match node {
+        Node::Number(number) => *number,
+        Node::Register(register) => {
+            let val = match register {
+                Register::A => registers.get_a(),
+                Register::X => registers.get_x(),
+                Register::L => registers.get_l(),
+                Register::B => registers.get_b(),
+                Register::S => registers.get_s(),
+                Register::T => registers.get_t(),
+                Register::Pc => registers.get_pc(),
+                Register::Sw => registers.get_sw(),
+            };
+            val as i64
+        }
+        Node::Label(name) => {
+            processor.get_symbol(name).ok_or_else(|| format!("Unknown label {name}"))? as i64
+        }
+        Node::Word(node) => u8arr_to_i24(memory.get_word(address(node, 3)?)) as i64,
+        Node::Byte(node) => memory.get_byte(address(node, 1)?) as i64,
+        Node::Neg(node) => evaluate(node, processor)?.wrapping_neg(),
+        Node::Not(node) => (evaluate(node, processor)? == 0) as i64,
+        Node::Binary(Operator::Or, left, right) => {
+            (evaluate(left, processor)? != 0 || evaluate(right, processor)? != 0) as i64
+        }
+        Node::Binary(Operator::And, left, right) => {
+            (evaluate(left, processor)? != 0 && evaluate(right, processor)? != 0) as i64
+        }
+        Node::Binary(operator, left, right) => {
+            let left = evaluate(left, processor)?;
+            let right = evaluate(right, processor)?;
+            match operator {
+                Operator::Equal => (left == right) as i64,
+                Operator::NotEqual => (left != right) as i64,
+                Operator::Less => (left < right) as i64,
+                Operator::LessEqual => (left <= right) as i64,
+                Operator::Greater => (left > right) as i64,
+                Operator::GreaterEqual => (left >= right) as i64,
+                Operator::BitOr => left | right,
+                Operator::BitAnd => left & right,
+                Operator::Add => left.wrapping_add(right),
+                Operator::Sub => left.wrapping_sub(right),
+                Operator::Mul => left.wrapping_mul(right),
+                Operator::Div | Operator::Rem if right == 0 => {
+                    return Err("Division by zero".to_string());
+                }
+                Operator::Div => left.wrapping_div(right),
+                Operator::Rem => left.wrapping_rem(right),
+                Operator::Or | Operator::And => unreachable!("short circuited above"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str, processor: &Processor) -> Result<i64, String> {
+        Expression::parse(text)?.evaluate(processor)
+    }
+
+    #[test]
+    fn precedence_and_numbers() {
+        let handle = Processor::new_handle();
+        let processor = handle.lock().unwrap();
+        assert_eq!(eval("1 + 2 * 3", &processor), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3", &processor), Ok(9));
+        assert_eq!(eval("0x10 - 1 - 1", &processor), Ok(14));
+        assert_eq!(eval("1 | 6 & 3", &processor), Ok(3));
+        assert_eq!(eval("-7 % 4 == -3 && !0", &processor), Ok(1));
+        assert_eq!(eval("1 < 2 || 1 / 0", &processor), Ok(1));
+    }
+
+    #[test]
+    fn registers_labels_and_memory() {
+        let handle = Processor::new_handle();
+        let mut processor = handle.lock().unwrap();
+        processor.machine.registers.set_x(0x10);
+        processor.add_symbol("buf", 0x100);
+        processor.machine.memory.set_word(0x100, [0x00, 0x01, 0x02]);
+        assert_eq!(eval("X == 0x10", &processor), Ok(1));
+        assert_eq!(eval("[buf]", &processor), Ok(0x102));
+        assert_eq!(eval("byte[buf + 2]", &processor), Ok(2));
+        assert!(Expression::parse("[buf] > 3 && X").unwrap().is_true(&processor).unwrap());
+    }
+
+    #[test]
+    fn reports_errors() {
+        let handle = Processor::new_handle();
+        let processor = handle.lock().unwrap();
+        assert_eq!(eval("nope", &processor), Err("Unknown label nope".to_string()));
+        assert_eq!(eval("1 / (X - X)", &processor), Err("Division by zero".to_string()));
+        assert_eq!(eval("[0x100000]", &processor), Err("Address 100000 out of range".to_string()));
+        assert!(Expression::parse("1 +").is_err());
+        assert!(Expression::parse("(1").is_err());
+        assert!(Expression::parse("1 2").is_err());
+        assert!(Expression::parse("0xZZ").is_err());
+        assert!(Expression::parse("A $ 1").is_err());
+    }
+}
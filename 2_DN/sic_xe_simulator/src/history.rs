@@ -0,0 +1,37 @@
+use std::collections::VecDeque;
+
+use crate::{call_stack::CallStack, machine::registers::Registers};
+
+/// oldest records are dropped beyond this
+const MAX_RECORDS: usize = 100_000;
+
+/// State before one executed instruction (including any interrupt it caused).
+pub struct UndoRecord {
+    pub registers: Registers,
+    pub interval_timer: i32,
+    /// cycles executed before it
+    pub cycles: u64,
+    /// (address, old byte) in write order
+    pub memory: Vec<(usize, u8)>,
+    /// before a JSUB or RSUB, None -> the instruction didn't change it
+    pub call_stack: Option<CallStack>,
+}
+
+/// Undo journal for reverse execution. Device I/O and storage keys are not undone.
+pub struct History {
+    records: VecDeque<UndoRecord>,
+}
+
+impl History {
+    pub fn new() -> Self { Self { records: VecDeque::new() } }
+
+    pub fn push(&mut self, record: UndoRecord) {
+        if self.records.len() == MAX_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+    pub fn pop(&mut self) -> Option<UndoRecord> { self.records.pop_back() }
+    pub fn clear(&mut self) { self.records.clear(); }
+    pub fn len(&self) -> usize { self.records.len() }
+}
@@ -0,0 +1,418 @@
+use std::{collections::HashMap, fs};
+
+use crate::machine::Machine;
+
+// Object program records (addresses and lengths in hex, names padded to 6 characters)
+//
+// H name(6) start(6) length(6)
+// D (name(6) address(6))*    EXTDEF
+// R name(6)*                 EXTREF
+// T address(6) length(2) bytes(2 each)
+// M address(6) half bytes(2) [(+|-) symbol(6)]
+// E [entry(6)]
+//
+// A file holds one or more control sections, each from its H to its E record.
+// T, D and E addresses are absolute for the assembled start, M addresses are relative to it.
+// An M record without a symbol adds how far the section was moved from its assembled start,
+// one with a symbol adds (or subtracts) the symbol's address from the external symbol table:
+// section names and D record symbols of every linked section.
+
+/// longest field an M record can modify: 5 (F4 address) or 6 (word) half bytes in SIC/XE
+const MAX_HALF_BYTES: usize = 6;
+
+/// M record, modifies the field at address
+#[derive(Debug, Clone)]
+pub struct Modification {
+    /// from the start of the section
+    pub address: usize,
+    /// length of the field in half bytes, an odd length skips the high half of the first byte
+    pub half_bytes: usize,
+    /// None -> relocate by the load offset
+    pub symbol: Option<String>,
+    /// - instead of +
+    pub negative: bool,
+}
+
+/// One control section of an .obj file
+#[derive(Debug, Clone)]
+pub struct ObjectProgram {
+    pub name: String,
+    /// assembled start address
+    pub start: usize,
+    pub length: usize,
+    /// D records: (name, absolute address)
+    pub definitions: Vec<(String, usize)>,
+    /// R records
+    pub references: Vec<String>,
+    /// (absolute address, bytes)
+    pub texts: Vec<(usize, Vec<u8>)>,
+    pub modifications: Vec<Modification>,
+    /// absolute, None -> no address in the E record
+    pub entry: Option<usize>,
+}
+
+/// Result of linking and loading
+pub struct Linked {
+    /// first entry address given in an E record, else the start of the first section
+    pub entry: usize,
+    /// external symbol table: section names and D record symbols with their load addresses
+    pub symbols: Vec<(String, usize)>,
+}
+
+fn hex(line: &str, range: std::ops::Range<usize>, what: &str) -> Result<usize, String> {
+    let field = line.get(range).ok_or_else(|| format!("Missing {what}"))?;
+    usize::from_str_radix(field, 16).map_err(|_| format!("Invalid {what} '{field}'"))
+}
+
+/// names padded to 6 characters, the last one may be shorter
+fn names(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(6)
+        .map(|name| name.iter().collect::<String>().trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+impl ObjectProgram {
+    /// all control sections of the file
+    pub fn read(file_name: &str) -> Result<Vec<Self>, String> {
+        let text = fs::read_to_string(file_name)
+            .map_err(|error| format!("Could not read {file_name}: {error}"))?;
+        Self::parse(&text).map_err(|error| format!("{file_name}: {error}"))
+    }
+
+    pub fn parse(text: &str) -> Result<Vec<Self>, String> {
+        let mut programs = Vec::new();
+        // section between its H and E record
+        let mut program: Option<ObjectProgram> = None;
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let ended = Self::parse_record(&mut program, line)
+                .map_err(|error| format!("line {}: {error}", index + 1))?;
+            if ended {
+                let program = program.take().expect("E record ends a section");
+                program.validate()?;
+                programs.push(program);
+            }
+        }
+        if let Some(program) = program {
+            return Err(format!("Missing E record of {}", program.name));
+        }
+        if programs.is_empty() {
+            return Err("Missing H record".to_string());
+        }
+        Ok(programs)
+    }
+
+    /// return: the record was E
+    fn parse_record(program: &mut Option<ObjectProgram>, line: &str) -> Result<bool, String> {
+        let kind = line.chars().next().unwrap_or(' ');
+        if kind == 'H' {
+            if let Some(program) = program {
+                return Err(format!("H record before the E record of {}", program.name));
+            }
+            let name = line.get(1..7).ok_or("Missing program name")?.trim().to_string();
+            let start = hex(line, 7..13, "start address")?;
+            let length = hex(line, 13..19, "program length")?;
+            *program = Some(ObjectProgram {
+                name,
+                start,
+                length,
+                definitions: Vec::new(),
+                references: Vec::new(),
+                texts: Vec::new(),
+                modifications: Vec::new(),
+                entry: None,
+            });
+            return Ok(false);
+        }
+
+        let program = program.as_mut().ok_or(format!("{kind} record outside of a section"))?;
+        match kind {
+            'D' => {
+                if !(line.len() - 1).is_multiple_of(12) {
+                    return Err("D record fields aren't name(6) address(6) pairs".to_string());
+                }
+                for offset in (1..line.len()).step_by(12) {
+                    let name = line.get(offset..offset + 6).ok_or("Invalid D record name")?;
+                    let address = hex(line, offset + 6..offset + 12, "D record address")?;
+                    program.definitions.push((name.trim().to_string(), address));
+                }
+            }
+            'R' => program.references.extend(names(line.get(1..).unwrap_or(""))),
+            'T' => {
+                let address = hex(line, 1..7, "address")?;
+                let len = hex(line, 7..9, "length")?;
+                if line.len() != 9 + 2 * len {
+                    let found = line.len().saturating_sub(9) / 2;
+                    return Err(format!("T record has {found} bytes, expected {len}"));
+                }
+                let bytes = (0..len)
+                    .map(|i| hex(line, 9 + 2 * i..11 + 2 * i, "byte").map(|byte| byte as u8))
+                    .collect::<Result<Vec<u8>, String>>()?;
+                program.texts.push((address, bytes));
+            }
+            'M' => {
+                let address = hex(line, 1..7, "address")?;
+                let half_bytes = hex(line, 7..9, "length")?;
+                let (negative, symbol) = match line.get(9..10) {
+                    None => (false, None),
+                    Some(sign @ ("+" | "-")) => (sign == "-", Some(line[10..].trim().to_string())),
+                    Some(_) => return Err("Expected + or - before the symbol".to_string()),
+                };
+                program.modifications.push(Modification { address, half_bytes, symbol, negative });
+            }
+            'E' => {
+                if line.len() > 1 {
+                    program.entry = Some(hex(line, 1..7, "entry address")?);
+                }
+                return Ok(true);
+            }
+            _ => return Err(format!("Unknown record {kind}")),
+        }
+        Ok(false)
+    }
+
+    /// everything stays inside the length from the H record
+    fn validate(&self) -> Result<(), String> {
+        let end = self.start + self.length;
+        for (address, bytes) in &self.texts {
+            if *address < self.start || address + bytes.len() > end {
+                return Err(format!(
+                    "T record at {address:06X} is outside {} ({:06X}..{end:06X})",
+                    self.name, self.start
+                ));
+            }
+        }
+        for modification in &self.modifications {
+            let half_bytes = modification.half_bytes;
+            if half_bytes == 0 || half_bytes > MAX_HALF_BYTES {
+                return Err(format!(
+                    "M record at {:06X} modifies {half_bytes} half bytes, at most {MAX_HALF_BYTES}",
+                    modification.address
+                ));
+            }
+            if modification.address + half_bytes.div_ceil(2) > self.length {
+                return Err(format!(
+                    "M record at {:06X} is outside {}",
+                    modification.address, self.name
+                ));
+            }
+        }
+        for (name, address) in &self.definitions {
+            if *address < self.start || *address > end {
+                return Err(format!("{name} ({address:06X}) is defined outside {}", self.name));
+            }
+        }
+        if let Some(entry) = self.entry.filter(|entry| *entry < self.start || *entry > end) {
+            return Err(format!("Entry {entry:06X} is outside {}", self.name));
+        }
+        Ok(())
+    }
+}
+
+/// Link the sections one after another from address (None -> where the first one was assembled)
+/// and write them into memory. Nothing is written if a symbol is undefined or defined twice,
+/// all of them are reported, one per line.
+pub fn link(
+    machine: &mut Machine,
+    programs: &[ObjectProgram],
+    address: Option<usize>,
+) -> Result<Linked, String> {
+    let first = programs.first().ok_or("Nothing to load")?;
+
+    // pass 1: section addresses and the external symbol table
+    let mut section_address = address.unwrap_or(first.start);
+    let mut section_addresses = Vec::new();
+    let mut symbols: Vec<(String, usize)> = Vec::new();
+    let mut table: HashMap<&str, usize> = HashMap::new();
+    let mut errors = Vec::new();
+    for program in programs {
+        if !machine.memory.is_valid(section_address, program.length) {
+            let (name, length) = (&program.name, program.length);
+            errors.push(format!("{name} ({length:X} bytes) doesn't fit at {section_address:06X}"));
+        }
+        let definitions = program.definitions.iter().map(|(name, address)| {
+            (name.as_str(), section_address + address - program.start)
+        });
+        let section = std::iter::once((program.name.as_str(), section_address));
+        for (name, address) in section.chain(definitions) {
+            if table.contains_key(name) {
+                errors.push(format!("Duplicate external symbol {name} in {}", program.name));
+            } else {
+                table.insert(name, address);
+                symbols.push((name.to_string(), address));
+            }
+        }
+        section_addresses.push(section_address);
+        section_address += program.length;
+    }
+
+    // every reference and modification must resolve
+    let mut deltas = Vec::new();
+    for (program, section_address) in programs.iter().zip(&section_addresses) {
+        let undefined =
+            |name: &str| format!("Undefined external symbol {name} in {}", program.name);
+        for name in &program.references {
+            if !table.contains_key(name.as_str()) {
+                errors.push(undefined(name));
+            }
+        }
+        let offset = *section_address as i64 - program.start as i64;
+        for modification in &program.modifications {
+            let delta = match &modification.symbol {
+                None => offset,
+                Some(symbol) => match table.get(symbol.as_str()) {
+                    Some(address) => *address as i64,
+                    None => {
+                        // R records are reported above
+                        if !program.references.contains(symbol) {
+                            errors.push(undefined(symbol));
+                        }
+                        continue;
+                    }
+                },
+            };
+            deltas.push(if modification.negative { -delta } else { delta });
+        }
+    }
+    if !errors.is_empty() {
+        errors.dedup();
+        return Err(errors.join("\n"));
+    }
+
+    // pass 2: load text and apply modifications
+    let mut deltas = deltas.into_iter();
+    for (program, section_address) in programs.iter().zip(&section_addresses) {
+        let offset = *section_address as i64 - program.start as i64;
+        for (text_address, bytes) in &program.texts {
+            let text_address = (*text_address as i64 + offset) as usize;
+            for (i, byte) in bytes.iter().enumerate() {
+                machine.memory.set_byte(text_address + i, *byte);
+            }
+        }
+        for modification in &program.modifications {
+            let delta = deltas.next().expect("one delta per modification");
+            let address = section_address + modification.address;
+            modify(machine, address, modification.half_bytes, delta);
+        }
+    }
+
+    let entry = programs
+        .iter()
+        .zip(&section_addresses)
+        .find_map(|(program, section_address)| {
+            program.entry.map(|entry| section_address + entry - program.start)
+        })
+        .unwrap_or(section_addresses[0]);
+    Ok(Linked { entry, symbols })
+}
+
+/// add delta to the field of half_bytes at address, bits in front of it stay
+fn modify(machine: &mut Machine, address: usize, half_bytes: usize, delta: i64) {
+    let len = half_bytes.div_ceil(2);
+    let mut value: i64 = 0;
+    for i in 0..len {
+        value = (value << 8) | machine.memory.get_byte(address + i) as i64;
+    }
+    let mask = (1i64 << (4 * half_bytes)) - 1;
+    let value = (value & !mask) | (value.wrapping_add(delta) & mask);
+    for i in 0..len {
+        machine.memory.set_byte(address + i, (value >> (8 * (len - 1 - i))) as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// load text at address (None -> where it was assembled)
+    fn load(text: &str, address: Option<usize>) -> Result<(Machine, Linked), String> {
+        let programs = ObjectProgram::parse(text)?;
+        let mut machine = Machine::new();
+        let linked = link(&mut machine, &programs, address)?;
+        Ok((machine, linked))
+    }
+
+    #[test]
+    fn parses_records() {
+        let programs = ObjectProgram::parse("HPROG  000100000006\nT0001000303000A\nE000100\n")
+            .unwrap();
+        assert_eq!(programs.len(), 1);
+        let program = &programs[0];
+        assert_eq!((program.name.as_str(), program.start, program.length), ("PROG", 0x100, 6));
+        assert_eq!(program.texts, vec![(0x100, vec![0x03, 0x00, 0x0A])]);
+        assert_eq!(program.entry, Some(0x100));
+    }
+
+    #[test]
+    fn rejects_bad_records() {
+        let error = |text| ObjectProgram::parse(text).unwrap_err();
+        assert!(error("T000000030000\n").contains("outside of a section"));
+        assert!(error("HPROG  000000000003\nT000000030000\nE\n").contains("expected 3"));
+        assert!(error("HPROG  000000000003\nT0000000400000000\nE\n").contains("outside"));
+        assert!(error("HPROG  000000000003\nT00000003000000\n").contains("Missing E"));
+        assert!(error("HPROG  000000000003\nM00000106\nE\n").contains("outside"));
+        assert!(error("HPROG  000000000003\nM00000000\nE\n").contains("half bytes"));
+    }
+
+    #[test]
+    fn rejects_m_records_longer_than_a_word() {
+        // used to overflow the mask of the field
+        let text = "HPROG  000000000100\nM00000120\nE\n";
+        let error = ObjectProgram::parse(text).unwrap_err();
+        assert!(error.contains("32 half bytes"), "{error}");
+        let text = "HPROG  000000000100\nM00000107\nE\n";
+        assert!(ObjectProgram::parse(text).is_err());
+    }
+
+    #[test]
+    fn relocates_by_the_load_offset() {
+        // +JSUB 0x00010 at 0, modified as a 5 half byte address, and a WORD 0x000010 at 4
+        let text = "HPROG  000000000007\nT000000074B100010000010\nM00000105\nM00000406\n\
+                    E000000\n";
+        let (machine, linked) = load(text, Some(0x1000)).unwrap();
+        assert_eq!(linked.entry, 0x1000);
+        assert_eq!(machine.memory.get_word(0x1000), [0x4B, 0x10, 0x10]);
+        assert_eq!(machine.memory.get_byte(0x1003), 0x10);
+        assert_eq!(machine.memory.get_word(0x1004), [0x00, 0x10, 0x10]);
+    }
+
+    #[test]
+    fn odd_field_keeps_the_high_half_of_its_first_byte() {
+        let text = "HPROG  000000000003\nT00000003FFFFFF\nM00000005\nE\n";
+        let (machine, _) = load(text, Some(0x10)).unwrap();
+        assert_eq!(machine.memory.get_word(0x10), [0xF0, 0x00, 0x0F]);
+    }
+
+    /// MAIN calls SUBR of the SUB section with +JSUB
+    const MAIN: &str = "HMAIN  000000000004\nRSUBR\nT000000044B100000\nM00000105+SUBR\nE000000\n";
+    const SUB: &str = "HSUB   000000000003\nDSUBR  000001\nT000000034F0000\nE\n";
+
+    #[test]
+    fn links_sections_through_external_symbols() {
+        let (machine, linked) = load(&format!("{MAIN}{SUB}"), Some(0x1000)).unwrap();
+        assert_eq!(linked.entry, 0x1000);
+        let symbols = [("MAIN", 0x1000), ("SUB", 0x1004), ("SUBR", 0x1005)];
+        let symbols = symbols.map(|(name, address)| (name.to_string(), address));
+        assert_eq!(linked.symbols, symbols);
+        assert_eq!(machine.memory.get_word(0x1000), [0x4B, 0x10, 0x10]);
+        assert_eq!(machine.memory.get_byte(0x1003), 0x05);
+        assert_eq!(machine.memory.get_word(0x1004), [0x4F, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn reports_undefined_and_duplicate_symbols() {
+        let error = load(MAIN, None).err().unwrap();
+        assert_eq!(error, "Undefined external symbol SUBR in MAIN");
+        let error = load(&format!("{MAIN}{SUB}{SUB}"), None).err().unwrap();
+        let lines: Vec<&str> = error.lines().collect();
+        let duplicates =
+            ["SUB", "SUBR"].map(|name| format!("Duplicate external symbol {name} in SUB"));
+        assert_eq!(lines, duplicates);
+    }
+}
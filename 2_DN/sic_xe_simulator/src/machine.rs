@@ -1,23 +1,40 @@
-mod devices;
+pub mod decode_cache;
+pub mod device_config;
+pub mod devices;
+pub mod float;
+pub mod interrupts;
 mod memory;
 pub mod opcodes;
-mod registers;
+pub mod registers;
+pub mod screen;
+pub mod snapshot;
+pub mod watchpoints;
 
+use std::path::Path;
+
+use devices::buffer_device::BufferDevice;
 use devices::device::Device;
 use devices::err_device::ErrDevice;
 use devices::file_device::FileDevice;
-use devices::input_device::InputDevice;
+use devices::input_device::{InputDevice, InputQueue};
 use devices::output_device::OutputDevice;
+use interrupts::{InterruptClass, Interrupts};
 use memory::Memory;
 use registers::Registers;
+use screen::Screen;
 
 const MAX_DEVICES: usize = 256;
 
 pub struct Machine {
     pub registers: Registers,
     pub memory: Memory,
+    pub interrupts: Interrupts,
+    /// video memory region, None -> no screen
+    pub screen: Option<Screen>,
     /// accessable with get_device and set_device
     devices: Vec<Box<dyn Device>>,
+    /// indices of devices that tick, see Device::is_clocked
+    clocked: Vec<usize>,
 }
 
 impl Machine {
@@ -26,32 +43,70 @@ impl Machine {
         Self {
             registers: Registers::new(),
             memory: Memory::new(),
-            devices: Machine::device_init(),
+            interrupts: Interrupts::new(),
+            screen: Some(Screen::new()),
+            devices: Machine::device_init(Path::new("")),
+            clocked: Vec::new(),
         }
     }
 
-    fn device_init() -> Vec<Box<dyn Device>> {
+    /// dev_dir: where the <hex>.dev files of devices 3..FF are
+    fn device_init(dev_dir: &Path) -> Vec<Box<dyn Device>> {
         let mut vec: Vec<Box<dyn Device>> = Vec::with_capacity(MAX_DEVICES);
-        vec.push(Box::new(InputDevice {}));
+        vec.push(Box::new(InputDevice::new()));
         vec.push(Box::new(OutputDevice { write_buffer: String::new() }));
         vec.push(Box::new(ErrDevice {}));
         for i in 3..MAX_DEVICES {
             let hex_string = format!("{:X}", i);
-            vec.push(Box::new(FileDevice::new(hex_string + ".dev")));
+            let path = dev_dir.join(hex_string + ".dev");
+            vec.push(Box::new(FileDevice::new(path.display().to_string())));
         }
         vec
     }
+    pub fn has_device(&self, index: usize) -> bool { index < self.devices.len() }
     pub fn get_device(&mut self, index: usize) -> &mut Box<dyn Device> { &mut self.devices[index] }
     pub fn set_device(&mut self, index: usize, device: Box<dyn Device>) -> () {
+        self.clocked.retain(|clocked| *clocked != index);
+        if device.is_clocked() {
+            self.clocked.push(index);
+        }
         self.devices[index] = device;
     }
+    /// back to the default devices, with the <hex>.dev files in dev_dir
+    pub fn reset_devices(&mut self, dev_dir: &Path) {
+        self.devices = Machine::device_init(dev_dir);
+        self.clocked.clear();
+    }
+
+    /// After every instruction: count down the interval timer and the timer devices.
+    /// steps: instructions executed so far
+    /// cycles: the instruction took
+    pub fn tick(&mut self, steps: u64, cycles: u64) {
+        self.interrupts.tick();
+        for index in &self.clocked {
+            if self.devices[*index].tick(steps, cycles) {
+                self.interrupts.raise(InterruptClass::Timer, 0);
+            }
+        }
+    }
+    /// a timer is running, STI or a timer device
+    pub fn has_armed_timer(&self) -> bool {
+        self.interrupts.get_interval_timer() > 0
+            || self.clocked.iter().any(|index| self.devices[*index].is_armed())
+    }
 
-    pub fn output_text(&self) -> &str {
-        // device 1 is OutputDevice
-        &self.devices[1]
-            .as_any()
-            .downcast_ref::<OutputDevice>()
-            .expect("Device 1 is not OutputDevice")
-            .write_buffer
+    /// queue of an InputDevice, None -> device index isn't one
+    pub fn input_queue(&self, index: usize) -> Option<InputQueue> {
+        let device = self.devices.get(index)?;
+        device.as_any().downcast_ref::<InputDevice>().map(InputDevice::get_queue)
+    }
+
+    /// None -> device 1 isn't kept in memory (OutputDevice or BufferDevice)
+    pub fn output_text(&self) -> Option<&str> {
+        let device = self.devices[1].as_any();
+        if let Some(output) = device.downcast_ref::<OutputDevice>() {
+            return Some(&output.write_buffer);
+        }
+        device.downcast_ref::<BufferDevice>().map(|buffer| buffer.write_buffer.as_str())
     }
 }
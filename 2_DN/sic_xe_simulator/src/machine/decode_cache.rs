@@ -0,0 +1,152 @@
+use crate::{
+    machine::{interrupts::ProgramCheck, opcodes::Opcode},
+    sic_xe::{get_format_sic_f3_f4_bits, is_format_f4, is_format_sic, FormatSicF3F4Bits},
+};
+
+/// addresses per page of the cache, pages are allocated on the first instruction in them
+const PAGE_SIZE: usize = 0x1000;
+/// longest instruction (F4)
+const MAX_LEN: usize = 4;
+
+/// Instruction decoded from memory, ready to execute
+#[derive(Clone, Copy)]
+pub enum Decoded {
+    F1(Opcode),
+    /// opcode, r1 r2
+    F2(Opcode, u8),
+    /// opcode, n i x b p e, address field (15b SIC, 12b F3, 20b F4)
+    SicF3F4(Opcode, FormatSicF3F4Bits, usize),
+}
+
+impl Decoded {
+    /// Decode the instruction at address from its bytes.
+    /// return:
+    /// \   Ok -> the instruction
+    /// \   Err -> (program check, bytes fetched before it was raised)
+    pub fn read(
+        get_byte: impl Fn(usize) -> Option<u8>,
+        address: usize,
+    ) -> Result<Self, (ProgramCheck, usize)> {
+        let fetch = |i: usize| {
+            get_byte(address + i).ok_or((ProgramCheck::AddressOutOfRange(address + i), i + 1))
+        };
+        let b1 = fetch(0)?;
+        let opcode = Opcode::from_byte(b1 & 0xFC).ok_or((ProgramCheck::IllegalInstruction(b1), 1))?;
+        if opcode.is_format_1() {
+            return Ok(Decoded::F1(opcode));
+        }
+        let b2 = fetch(1)?;
+        if opcode.is_format_2() {
+            return Ok(Decoded::F2(opcode, b2));
+        }
+        let b3 = fetch(2)? as usize;
+        let bits = get_format_sic_f3_f4_bits(&b1, &b2);
+        let address_field = if is_format_sic(&bits) {
+            ((b2 & 0x7F) as usize) << 8 | b3
+        } else if is_format_f4(&bits) {
+            ((b2 & 0x0F) as usize) << 16 | b3 << 8 | fetch(3)? as usize
+        } else {
+            ((b2 & 0x0F) as usize) << 8 | b3
+        };
+        Ok(Decoded::SicF3F4(opcode, bits, address_field))
+    }
+
+    /// in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            Decoded::F1(_) => 1,
+            Decoded::F2(..) => 2,
+            Decoded::SicF3F4(_, bits, _) if !is_format_sic(bits) && is_format_f4(bits) => 4,
+            Decoded::SicF3F4(..) => 3,
+        }
+    }
+}
+
+/// Decoded instructions by address, so a loop is decoded once. Every write to memory drops
+/// the instructions it overlaps.
+pub struct DecodeCache {
+    pages: Vec<Option<Box<[Option<Decoded>]>>>,
+}
+
+impl DecodeCache {
+    pub fn new(size: usize) -> Self { Self { pages: vec![None; size.div_ceil(PAGE_SIZE)] } }
+
+    pub fn get(&self, address: usize) -> Option<Decoded> {
+        let page = self.pages.get(address / PAGE_SIZE)?.as_ref()?;
+        page[address % PAGE_SIZE]
+    }
+
+    pub fn insert(&mut self, address: usize, decoded: Decoded) {
+        let Some(page) = self.pages.get_mut(address / PAGE_SIZE) else { return };
+        let page = page.get_or_insert_with(|| vec![None; PAGE_SIZE].into_boxed_slice());
+        page[address % PAGE_SIZE] = Some(decoded);
+    }
+
+    /// address..address + len was written
+    pub fn invalidate(&mut self, address: usize, len: usize) {
+        // an instruction starting up to MAX_LEN - 1 bytes before may reach into the range
+        for address in address.saturating_sub(MAX_LEN - 1)..address + len {
+            if let Some(Some(page)) = self.pages.get_mut(address / PAGE_SIZE) {
+                page[address % PAGE_SIZE] = None;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) { self.pages.fill(None); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(code: &[u8]) -> Result<Decoded, (ProgramCheck, usize)> {
+        Decoded::read(|address| code.get(address).copied(), 0)
+    }
+
+    #[test]
+    fn reads_each_format() {
+        // FIX, ADDR A,X, LDA 3 (SIC), LDA #3, +LDA #0x12345
+        let cases: [(&[u8], usize); 5] = [
+            (&[0xC4], 1),
+            (&[0x90, 0x01], 2),
+            (&[0x00, 0x00, 0x03], 3),
+            (&[0x01, 0x00, 0x03], 3),
+            (&[0x01, 0x11, 0x23, 0x45], 4),
+        ];
+        for (code, len) in cases {
+            assert_eq!(read(code).ok().map(|decoded| decoded.len()), Some(len));
+        }
+        let Ok(Decoded::SicF3F4(_, _, address)) = read(&[0x01, 0x11, 0x23, 0x45]) else {
+            panic!("not F4");
+        };
+        assert_eq!(address, 0x12345);
+    }
+
+    #[test]
+    fn read_faults_say_how_far_they_got() {
+        assert_eq!(read(&[0xFF]).err(), Some((ProgramCheck::IllegalInstruction(0xFF), 1)));
+        // +LDA cut off after 3 bytes
+        assert_eq!(read(&[0x01, 0x11, 0x23]).err(), Some((ProgramCheck::AddressOutOfRange(3), 4)));
+    }
+
+    #[test]
+    fn writes_drop_the_instructions_they_reach() {
+        let mut cache = DecodeCache::new(2 * PAGE_SIZE);
+        let decoded = read(&[0x00, 0x00, 0x03]).ok().unwrap();
+        cache.insert(0x10, decoded);
+        cache.insert(0x20, decoded);
+        assert!(cache.get(0x10).is_some());
+        assert!(cache.get(0x11).is_none());
+        assert!(cache.get(2 * PAGE_SIZE).is_none());
+
+        // past the longest instruction starting at 0x10, then its last byte
+        cache.invalidate(0x14, 1);
+        assert!(cache.get(0x10).is_some());
+        cache.invalidate(0x13, 1);
+        assert!(cache.get(0x10).is_none());
+        assert!(cache.get(0x20).is_some());
+
+        cache.clear();
+        assert!(cache.get(0x20).is_none());
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::machine::{
+    devices::{
+        buffer_device::BufferDevice,
+        busy_device::BusyDevice,
+        clock_device::ClockDevice,
+        device::{Device, Eof},
+        err_device::ErrDevice,
+        file_device::FileDevice,
+        input_device::InputDevice,
+        null_device::NullDevice,
+        output_device::OutputDevice,
+        socket_device::{Endpoint, SocketDevice},
+        stream_device::StreamDevice,
+        timer_device::TimerDevice,
+    },
+    Machine, MAX_DEVICES,
+};
+
+// Device map, TOML (or JSON for a .json file), e.g.
+//
+// dev_dir = "devices"         # <hex>.dev files of unlisted devices 3..FF
+//
+// [devices]
+// 0 = { type = "keyboard" }
+// 1 = { type = "buffer" }
+// F1 = { type = "file", read = "in.txt", write = "out.txt", eof = "wait" }
+// F2 = { type = "loopback" }
+// F3 = { type = "pipe", read = "sic.in", write = "sic.out" }
+// F4 = { type = "unix", path = "sic.sock", listen = true }
+// F5 = { type = "tcp", port = 5000 }          # connects to 127.0.0.1:5000
+// F6 = { type = "timer", interval = 1000 }    # timer interrupt every 1000 cycles
+// F7 = { type = "clock" }
+// F8 = { type = "null", busy = 3 }          # not ready for 3 TDs after every RD/WD
+//
+// Device numbers are hex, like in RD/WD/TD. Relative paths are relative to the config file.
+// Pipes and Unix sockets are only available on Unix.
+// eof: byte read at the end of input (default 0), or "wait" to retry RD until there is more.
+// Sockets default to "wait", TD is ready while received data is waiting to be read.
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum EofConfig {
+    Byte(u8),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum Backend {
+    /// default device 0, typed into from the TUI
+    Keyboard,
+    /// default device 1, shown in the Output pane
+    Output,
+    /// one file both read and written, like the default <hex>.dev files
+    Dev { path: String },
+    File {
+        read: Option<String>,
+        write: Option<String>,
+        #[serde(default)]
+        append: bool,
+        eof: Option<EofConfig>,
+    },
+    /// in-memory, reads data, collects writes
+    Buffer { data: Option<String>, eof: Option<EofConfig> },
+    Null,
+    /// reads back what was written
+    Loopback { eof: Option<EofConfig> },
+    Stdin { eof: Option<EofConfig> },
+    Stdout,
+    Stderr,
+    /// named pipes (mkfifo), the write end is opened on the first write
+    Pipe { read: Option<String>, write: Option<String>, eof: Option<EofConfig> },
+    /// Unix domain socket, listen -> the peer connects to us
+    Unix {
+        path: String,
+        #[serde(default)]
+        listen: bool,
+        eof: Option<EofConfig>,
+    },
+    /// TCP on 127.0.0.1
+    Tcp {
+        port: u16,
+        #[serde(default)]
+        listen: bool,
+        eof: Option<EofConfig>,
+    },
+    /// interval timer, interval: cycles, None -> stopped until the program starts it
+    Timer { interval: Option<u32> },
+    /// instruction count and wall time
+    Clock,
+}
+
+/// backend of a device number
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "serde_json::Value")]
+struct DeviceEntry {
+    backend: Backend,
+    /// TDs the device reports not ready after every RD/WD, see BusyDevice
+    busy: usize,
+}
+
+// busy is taken out before the backend, so its unknown keys are still reported
+impl TryFrom<serde_json::Value> for DeviceEntry {
+    type Error = String;
+
+    fn try_from(mut value: serde_json::Value) -> Result<Self, String> {
+        let busy = match value.as_object_mut().and_then(|entry| entry.remove("busy")) {
+            Some(busy) => busy.as_u64().ok_or("busy must be a count of TDs")? as usize,
+            None => 0,
+        };
+        let backend = serde_json::from_value(value).map_err(|error| error.to_string())?;
+        Ok(Self { backend, busy })
+    }
+}
+
+/// Which backend each device number uses, see the format above.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+    dev_dir: Option<String>,
+    #[serde(default)]
+    devices: BTreeMap<String, DeviceEntry>,
+    /// directory of the config file
+    #[serde(skip)]
+    base: PathBuf,
+}
+
+impl DeviceConfig {
+    pub fn read(file_name: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(file_name)
+            .map_err(|error| format!("Could not read {file_name}: {error}"))?;
+        let mut config: DeviceConfig = if file_name.ends_with(".json") {
+            serde_json::from_str(&text).map_err(|error| format!("{file_name}: {error}"))?
+        } else {
+            toml::from_str(&text).map_err(|error| format!("{file_name}: {error}"))?
+        };
+        config.base = Path::new(file_name).parent().unwrap_or(Path::new("")).to_path_buf();
+        Ok(config)
+    }
+
+    /// device index is in the map
+    pub fn has_device(&self, index: usize) -> bool {
+        self.devices.keys().any(|number| usize::from_str_radix(number, 16) == Ok(index))
+    }
+
+    fn path(&self, path: &str) -> PathBuf { self.base.join(path) }
+
+    /// Install the devices into machine, unlisted ones get the defaults.
+    pub fn apply(&self, machine: &mut Machine) -> Result<(), String> {
+        let dev_dir = self.path(self.dev_dir.as_deref().unwrap_or(""));
+        machine.reset_devices(&dev_dir);
+
+        for (number, entry) in &self.devices {
+            let index = usize::from_str_radix(number, 16)
+                .ok()
+                .filter(|index| *index < MAX_DEVICES)
+                .ok_or_else(|| format!("Invalid device number {number}"))?;
+            let mut device =
+                self.create(&entry.backend).map_err(|error| format!("Device {number}: {error}"))?;
+            if entry.busy > 0 {
+                device = Box::new(BusyDevice::new(device, entry.busy));
+            }
+            machine.set_device(index, device);
+        }
+        Ok(())
+    }
+
+    fn create(&self, backend: &Backend) -> Result<Box<dyn Device>, String> {
+        let open = |path: &str| {
+            File::open(self.path(path)).map_err(|error| format!("Could not open {path}: {error}"))
+        };
+        Ok(match backend {
+            Backend::Keyboard => Box::new(InputDevice::new()),
+            Backend::Output => Box::new(OutputDevice { write_buffer: String::new() }),
+            Backend::Dev { path } => {
+                Box::new(FileDevice::new(self.path(path).display().to_string()))
+            }
+            Backend::File { read, write, append, eof } => {
+                let reader = match read {
+                    Some(path) => Some(Box::new(open(path)?) as Box<dyn Read + Send>),
+                    None => None,
+                };
+                let writer = match write {
+                    Some(path) => {
+                        let file = OpenOptions::new()
+                            .write(true)
+                            .create(true)
+                            .append(*append)
+                            .truncate(!*append)
+                            .open(self.path(path))
+                            .map_err(|error| format!("Could not create {path}: {error}"))?;
+                        Some(Box::new(file) as Box<dyn Write + Send>)
+                    }
+                    None => None,
+                };
+                Box::new(StreamDevice::new(reader, writer).with_eof(parse_eof(eof)?))
+            }
+            Backend::Buffer { data, eof } => {
+                let data = data.as_deref().unwrap_or("").as_bytes();
+                Box::new(BufferDevice::new(data, parse_eof(eof)?))
+            }
+            Backend::Null => Box::new(NullDevice {}),
+            Backend::Loopback { eof } => Box::new(BufferDevice::loopback(parse_eof(eof)?)),
+            Backend::Stdin { eof } => {
+                let reader: Box<dyn Read + Send> = Box::new(io::stdin());
+                Box::new(StreamDevice::new(Some(reader), None).with_eof(parse_eof(eof)?))
+            }
+            Backend::Stdout => Box::new(StreamDevice::new(None, Some(Box::new(io::stdout())))),
+            Backend::Stderr => Box::new(ErrDevice {}),
+            Backend::Pipe { read, write, eof } => self.pipe(read, write, eof)?,
+            Backend::Unix { path, listen, eof } => {
+                Box::new(socket(Endpoint::Unix(self.path(path)), *listen, eof)?)
+            }
+            Backend::Tcp { port, listen, eof } => {
+                Box::new(socket(Endpoint::Tcp(*port), *listen, eof)?)
+            }
+            Backend::Timer { interval } => {
+                Box::new(TimerDevice::with_interval(interval.unwrap_or(0) & 0xFFFFFF))
+            }
+            Backend::Clock => Box::new(ClockDevice::new()),
+        })
+    }
+
+    #[cfg(unix)]
+    fn pipe(
+        &self,
+        read: &Option<String>,
+        write: &Option<String>,
+        eof: &Option<EofConfig>,
+    ) -> Result<Box<dyn Device>, String> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        // nonblocking, so TD reports not ready instead of waiting for a writer
+        let reader = match read {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .custom_flags(libc::O_NONBLOCK)
+                    .open(self.path(path))
+                    .map_err(|error| format!("Could not open {path}: {error}"))?;
+                Some(Box::new(file) as Box<dyn Read + Send>)
+            }
+            None => None,
+        };
+        let writer = write.as_ref().map(|path| {
+            Box::new(PipeWriter { path: self.path(path), file: None }) as Box<dyn Write + Send>
+        });
+        Ok(Box::new(StreamDevice::new(reader, writer).with_eof(parse_eof(eof)?)))
+    }
+
+    #[cfg(not(unix))]
+    fn pipe(
+        &self,
+        _read: &Option<String>,
+        _write: &Option<String>,
+        _eof: &Option<EofConfig>,
+    ) -> Result<Box<dyn Device>, String> {
+        Err("Named pipes are only supported on Unix".to_string())
+    }
+}
+
+fn parse_eof(eof: &Option<EofConfig>) -> Result<Eof, String> {
+    match eof {
+        None => Ok(Eof::Byte(0)),
+        Some(EofConfig::Byte(byte)) => Ok(Eof::Byte(*byte)),
+        Some(EofConfig::Name(name)) if name == "wait" => Ok(Eof::Wait),
+        Some(EofConfig::Name(name)) => Err(format!("Invalid eof {name}, expected a byte or wait")),
+    }
+}
+
+fn socket(
+    endpoint: Endpoint,
+    listen: bool,
+    eof: &Option<EofConfig>,
+) -> Result<SocketDevice, String> {
+    if cfg!(not(unix)) && matches!(endpoint, Endpoint::Unix(_)) {
+        return Err("Unix sockets are only supported on Unix".to_string());
+    }
+    let eof = if eof.is_some() { parse_eof(eof)? } else { Eof::Wait };
+    if listen {
+        SocketDevice::listen(endpoint.clone(), eof)
+            .map_err(|error| format!("Could not listen on {endpoint}: {error}"))
+    } else {
+        Ok(SocketDevice::connect(endpoint, eof))
+    }
+}
+
+/// Opening a pipe for writing blocks until there is a reader, so wait for the first write.
+#[cfg(unix)]
+struct PipeWriter {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+#[cfg(unix)]
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() {
+            self.file = Some(OpenOptions::new().write(true).open(&self.path)?);
+        }
+        self.file.as_mut().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<DeviceConfig, String> {
+        toml::from_str(text).map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn busy_wraps_the_backend() {
+        let config = parse("[devices]\nF8 = { type = \"loopback\", busy = 2 }\n").unwrap();
+        let mut machine = Machine::new();
+        config.apply(&mut machine).unwrap();
+        let device = machine.get_device(0xF8);
+        device.write(7);
+        assert!(!device.test());
+        assert!(!device.test());
+        assert!(device.test());
+        assert_eq!(device.read(), 7);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_devices() {
+        assert!(parse("[devices]\n1 = { type = \"loopback\", bussy = 2 }\n").is_err());
+        assert!(parse("[devices]\n1 = { type = \"nothing\" }\n").is_err());
+        let config = parse("[devices]\n100 = { type = \"null\" }\n").unwrap();
+        assert!(config.apply(&mut Machine::new()).unwrap_err().contains("Invalid device number"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pipe_reports_a_missing_fifo() {
+        let config =
+            parse("[devices]\nF3 = { type = \"pipe\", read = \"missing.fifo\" }\n").unwrap();
+        let error = config.apply(&mut Machine::new()).unwrap_err();
+        assert!(error.starts_with("Device F3: Could not open missing.fifo"), "{error}");
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn pipe_needs_unix() {
+        let config = parse("[devices]\nF3 = { type = \"pipe\" }\n").unwrap();
+        assert!(config.apply(&mut Machine::new()).unwrap_err().contains("only supported on Unix"));
+    }
+}
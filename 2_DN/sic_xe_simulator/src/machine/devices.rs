@@ -1,5 +1,12 @@
+pub mod buffer_device;
+pub mod busy_device;
+pub mod clock_device;
 pub mod device;
 pub mod err_device;
 pub mod file_device;
 pub mod input_device;
+pub mod null_device;
 pub mod output_device;
+pub mod socket_device;
+pub mod stream_device;
+pub mod timer_device;
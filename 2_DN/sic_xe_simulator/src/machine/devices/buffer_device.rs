@@ -0,0 +1,65 @@
+use crate::machine::{
+    devices::device::{Device, Eof},
+    snapshot::DeviceState,
+};
+use std::{any::Any, collections::VecDeque};
+
+/// In-memory device: reads its input, collects what is written.
+/// A loopback device reads back what was written to it instead.
+pub struct BufferDevice {
+    input: VecDeque<u8>,
+    pub write_buffer: String,
+    loopback: bool,
+    /// last access was a read, so an empty input means not ready
+    reading: bool,
+    eof: Eof,
+}
+
+impl BufferDevice {
+    pub fn new(data: &[u8], eof: Eof) -> Self {
+        let input = data.iter().copied().collect();
+        Self { input, write_buffer: String::new(), loopback: false, reading: false, eof }
+    }
+    pub fn loopback(eof: Eof) -> Self { Self { loopback: true, ..Self::new(&[], eof) } }
+}
+
+impl Device for BufferDevice {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn test(&mut self) -> bool { !self.reading || !self.input.is_empty() }
+    fn is_waiting(&mut self) -> bool { self.eof == Eof::Wait && self.input.is_empty() }
+
+    fn read(&mut self) -> u8 {
+        self.reading = true;
+        match (self.input.pop_front(), self.eof) {
+            (Some(byte), _) => byte,
+            (None, Eof::Byte(byte)) => byte,
+            (None, Eof::Wait) => 0,
+        }
+    }
+
+    fn write(&mut self, val: u8) {
+        self.reading = false;
+        if self.loopback {
+            self.input.push_back(val);
+        } else {
+            self.write_buffer.push(val as char);
+        }
+    }
+
+    fn save_state(&self) -> Option<DeviceState> {
+        Some(DeviceState::Buffer {
+            input: self.input.iter().copied().collect(),
+            written: self.write_buffer.clone(),
+            reading: self.reading,
+        })
+    }
+
+    fn restore_state(&mut self, state: &DeviceState) -> bool {
+        let DeviceState::Buffer { input, written, reading } = state else { return false };
+        self.input = input.iter().copied().collect();
+        self.write_buffer = written.clone();
+        self.reading = *reading;
+        true
+    }
+}
@@ -0,0 +1,46 @@
+use crate::machine::{devices::device::Device, snapshot::DeviceState};
+use std::any::Any;
+
+/// Wraps another device and reports not ready for `busy_cycles` tests after every read/write.
+pub struct BusyDevice {
+    device: Box<dyn Device>,
+    busy_cycles: usize,
+    /// tests left until ready
+    remaining: usize,
+}
+
+impl BusyDevice {
+    pub fn new(device: Box<dyn Device>, busy_cycles: usize) -> Self {
+        Self { device, busy_cycles, remaining: 0 }
+    }
+}
+
+impl Device for BusyDevice {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn test(&mut self) -> bool {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return false;
+        }
+        self.device.test()
+    }
+
+    fn read(&mut self) -> u8 {
+        self.remaining = self.busy_cycles;
+        self.device.read()
+    }
+
+    fn write(&mut self, val: u8) {
+        self.remaining = self.busy_cycles;
+        self.device.write(val);
+    }
+
+    fn is_waiting(&mut self) -> bool { self.device.is_waiting() }
+    fn is_clocked(&self) -> bool { self.device.is_clocked() }
+    fn tick(&mut self, steps: u64, cycles: u64) -> bool { self.device.tick(steps, cycles) }
+    fn is_armed(&self) -> bool { self.device.is_armed() }
+
+    fn save_state(&self) -> Option<DeviceState> { self.device.save_state() }
+    fn restore_state(&mut self, state: &DeviceState) -> bool { self.device.restore_state(state) }
+}
@@ -0,0 +1,77 @@
+use crate::machine::devices::device::Device;
+use std::{
+    any::Any,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// length of a clock reading
+const READING_LEN: usize = 12;
+
+/// Real-time clock. RD returns a 12 byte reading, most significant byte first:
+/// instructions executed before the first RD of it (6 bytes), then wall time in milliseconds
+/// since 1970-01-01 UTC (6 bytes). The reading is taken on its first byte,
+/// WD (any byte) starts a new one.
+pub struct ClockDevice {
+    /// instructions executed, as of the last tick
+    steps: u64,
+    reading: [u8; READING_LEN],
+    /// next byte of the reading, READING_LEN -> take a new one
+    read_index: usize,
+}
+
+impl ClockDevice {
+    pub fn new() -> Self { Self { steps: 0, reading: [0; READING_LEN], read_index: READING_LEN } }
+
+    fn take_reading(&mut self) {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH);
+        let millis = time.map_or(0, |time| time.as_millis());
+        self.reading[..6].copy_from_slice(&self.steps.to_be_bytes()[2..]);
+        self.reading[6..].copy_from_slice(&(millis as u64).to_be_bytes()[2..]);
+        self.read_index = 0;
+    }
+}
+
+impl Device for ClockDevice {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn test(&mut self) -> bool { true }
+
+    fn read(&mut self) -> u8 {
+        if self.read_index == READING_LEN {
+            self.take_reading();
+        }
+        let byte = self.reading[self.read_index];
+        self.read_index += 1;
+        byte
+    }
+
+    fn write(&mut self, _val: u8) { self.read_index = READING_LEN; }
+
+    fn is_clocked(&self) -> bool { true }
+
+    fn tick(&mut self, steps: u64, _cycles: u64) -> bool {
+        self.steps = steps;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_is_taken_on_its_first_byte() {
+        let mut clock = ClockDevice::new();
+        clock.tick(0x123, 1);
+        let first: Vec<u8> = (0..READING_LEN).map(|_| clock.read()).collect();
+        assert_eq!(first[..6], [0, 0, 0, 0, 0x01, 0x23]);
+        assert!(first[6..].iter().any(|byte| *byte != 0));
+
+        clock.tick(0x200, 1);
+        assert_eq!(clock.read(), 0);
+        // WD starts over with a new reading
+        clock.write(0);
+        let steps: Vec<u8> = (0..6).map(|_| clock.read()).collect();
+        assert_eq!(steps, [0, 0, 0, 0, 0x02, 0x00]);
+    }
+}
@@ -1,9 +1,37 @@
 use std::any::Any;
 
+use crate::machine::snapshot::DeviceState;
+
+/// What RD does at the end of input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eof {
+    /// reads this byte
+    Byte(u8),
+    /// waits for more input, see Device::is_waiting
+    Wait,
+}
+
 pub trait Device: Send + Any {
     fn as_any(&self) -> &dyn Any;
 
-    fn test(&self) -> bool;
+    /// TD: true -> device ready for the next read/write
+    fn test(&mut self) -> bool;
+    /// RD has no byte to read yet and is retried, instead of reading
+    fn is_waiting(&mut self) -> bool { false }
     fn read(&mut self) -> u8;
     fn write(&mut self, val: u8) -> ();
+
+    /// tick is called after every instruction
+    fn is_clocked(&self) -> bool { false }
+    /// steps: instructions executed so far
+    /// cycles: the instruction took, see timing
+    /// return: true -> raise the timer interrupt
+    fn tick(&mut self, _steps: u64, _cycles: u64) -> bool { false }
+    /// tick will raise an interrupt later
+    fn is_armed(&self) -> bool { false }
+
+    /// None -> the device has no state to save (or can't save it, like a host stream)
+    fn save_state(&self) -> Option<DeviceState> { None }
+    /// return: false -> state is of another kind of device, nothing changed
+    fn restore_state(&mut self, _state: &DeviceState) -> bool { false }
 }
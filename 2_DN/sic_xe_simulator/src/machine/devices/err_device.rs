@@ -9,7 +9,7 @@ pub struct ErrDevice {}
 impl Device for ErrDevice {
     fn as_any(&self) -> &dyn Any { self }
 
-    fn test(&self) -> bool { true }
+    fn test(&mut self) -> bool { true }
 
     fn read(&mut self) -> u8 { 0 }
 
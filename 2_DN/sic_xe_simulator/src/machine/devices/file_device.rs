@@ -1,40 +1,60 @@
-use crate::machine::devices::device::Device;
+use crate::machine::{devices::device::Device, snapshot::DeviceState};
 use std::{
     any::Any,
     fs::{File, OpenOptions},
-    io::{ErrorKind, Read, Write},
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
 };
 
 pub struct FileDevice {
     file_name: String,
     file: Option<File>,
+    /// created on first use or last written to, so always ready
+    output: bool,
 }
 
 impl FileDevice {
-    pub fn new(file_name: String) -> Self { Self { file_name, file: None } }
-    fn open_file(&mut self) -> &mut File {
+    pub fn new(file_name: String) -> Self { Self { file_name, file: None, output: false } }
+    fn open_file(&mut self) -> &mut File { self.try_open_file().expect("Could not open file") }
+    fn try_open_file(&mut self) -> io::Result<&mut File> {
         if self.file.is_none() {
+            // nothing to read from a file that isn't there yet
+            let created = !Path::new(&self.file_name).exists();
             self.file = Some(
                 OpenOptions::new()
                     .write(true)
                     .read(true)
                     .create(true)
-                    .open(self.file_name.clone())
-                    .expect("Could not open file"),
+                    .open(self.file_name.clone())?,
             );
+            self.output |= created;
         }
 
-        self.file.as_mut().unwrap()
+        Ok(self.file.as_mut().unwrap())
+    }
+    /// a file whose position or length can't be read is at EOF
+    fn at_eof(&mut self) -> bool {
+        let file = self.open_file();
+        match (file.stream_position(), file.metadata()) {
+            (Ok(position), Ok(metadata)) => position >= metadata.len(),
+            _ => true,
+        }
     }
 }
 
 impl Device for FileDevice {
     fn as_any(&self) -> &dyn Any { self }
 
-    fn test(&self) -> bool { true }
+    /// output files are always ready, input files are ready until the read position reaches EOF
+    fn test(&mut self) -> bool {
+        // opening decides whether a new file is an output
+        let at_eof = self.at_eof();
+        self.output || !at_eof
+    }
 
     fn read(&mut self) -> u8 {
         let mut buf = [0u8; 1];
+        self.output = false;
 
         // println!("READING file: {}", self.file_name);
         match self.open_file().read_exact(&mut buf) {
@@ -48,6 +68,68 @@ impl Device for FileDevice {
     }
 
     fn write(&mut self, val: u8) -> () {
+        self.output = true;
         let _ = self.open_file().write_all(&mut [val]).expect("File writing error");
     }
+
+    fn save_state(&self) -> Option<DeviceState> {
+        // unopened -> never used, nothing to save
+        let position = self.file.as_ref()?.stream_position().ok()?;
+        Some(DeviceState::File { position, output: self.output })
+    }
+
+    fn restore_state(&mut self, state: &DeviceState) -> bool {
+        let DeviceState::File { position, output } = state else { return false };
+        // a file that can't be opened or seeked keeps its state, and is reported
+        let Ok(file) = self.try_open_file() else { return false };
+        if file.seek(SeekFrom::Start(*position)).is_err() {
+            return false;
+        }
+        self.output = *output;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_file::TempFile;
+    use std::fs;
+
+    #[test]
+    fn empty_input_file_is_not_ready_before_the_first_read() {
+        let file = TempFile::with_contents("file_device_empty", b"");
+        let mut device = FileDevice::new(file.path().to_string());
+        assert!(!device.test());
+    }
+
+    #[test]
+    fn input_file_is_ready_until_eof() {
+        let file = TempFile::with_contents("file_device_input", b"AB");
+        let mut device = FileDevice::new(file.path().to_string());
+        assert!(device.test());
+        assert_eq!(device.read(), b'A');
+        assert!(device.test());
+        assert_eq!(device.read(), b'B');
+        assert!(!device.test());
+    }
+
+    #[test]
+    fn new_file_is_an_always_ready_output() {
+        let file = TempFile::new("file_device_output");
+        let mut device = FileDevice::new(file.path().to_string());
+        assert!(device.test());
+        device.write(b'X');
+        assert!(device.test());
+        assert_eq!(fs::read(file.path()).unwrap(), b"X");
+    }
+
+    #[test]
+    fn unopenable_file_fails_to_restore() {
+        // inside a directory that doesn't exist
+        let file = TempFile::new("file_device_missing");
+        let mut device = FileDevice::new(format!("{}/input.dev", file.path()));
+        let state = DeviceState::File { position: 1, output: false };
+        assert!(!device.restore_state(&state));
+    }
 }
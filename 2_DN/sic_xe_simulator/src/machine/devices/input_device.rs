@@ -1,21 +1,71 @@
-use crate::machine::devices::device::Device;
+use crate::machine::{devices::device::Device, snapshot::DeviceState};
 use std::{
     any::Any,
-    io::{self, Read},
+    collections::VecDeque,
+    sync::{Arc, Mutex},
 };
 
-pub struct InputDevice {}
+/// Bytes typed into the device, shared with whoever feeds it (the TUI input mode).
+pub type InputQueue = Arc<Mutex<VecDeque<u8>>>;
+
+/// Keyboard: reads from its queue, TD reports not ready while the queue is empty.
+pub struct InputDevice {
+    queue: InputQueue,
+}
+
+impl InputDevice {
+    pub fn new() -> Self { Self { queue: Arc::new(Mutex::new(VecDeque::new())) } }
+
+    pub fn get_queue(&self) -> InputQueue { Arc::clone(&self.queue) }
+}
 
 impl Device for InputDevice {
     fn as_any(&self) -> &dyn Any { self }
 
-    fn test(&self) -> bool { true }
+    fn test(&mut self) -> bool { !self.queue.lock().unwrap().is_empty() }
+    /// RD waits for a key without blocking the processor
+    fn is_waiting(&mut self) -> bool { !self.test() }
 
-    fn read(&mut self) -> u8 {
-        let mut buf = [0];
-        io::stdin().read_exact(&mut buf).expect("Stdin error");
-        buf[0]
-    }
+    fn read(&mut self) -> u8 { self.queue.lock().unwrap().pop_front().unwrap_or(0) }
 
     fn write(&mut self, _val: u8) -> () {}
+
+    fn save_state(&self) -> Option<DeviceState> {
+        Some(DeviceState::Keyboard { queue: self.queue.lock().unwrap().iter().copied().collect() })
+    }
+
+    /// the queue stays shared, its contents are replaced
+    fn restore_state(&mut self, state: &DeviceState) -> bool {
+        let DeviceState::Keyboard { queue } = state else { return false };
+        *self.queue.lock().unwrap() = queue.iter().copied().collect();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_what_was_typed_into_its_queue() {
+        let mut device = InputDevice::new();
+        let queue = device.get_queue();
+        assert!(!device.test());
+        assert!(device.is_waiting());
+        queue.lock().unwrap().extend(b"hi");
+        assert!(device.test());
+        assert_eq!((device.read(), device.read()), (b'h', b'i'));
+        assert!(!device.test());
+    }
+
+    #[test]
+    fn restoring_keeps_the_queue_shared() {
+        let mut device = InputDevice::new();
+        let queue = device.get_queue();
+        queue.lock().unwrap().push_back(b'a');
+        let state = device.save_state().unwrap();
+        device.read();
+        assert!(device.restore_state(&state));
+        assert_eq!(queue.lock().unwrap().front(), Some(&b'a'));
+    }
 }
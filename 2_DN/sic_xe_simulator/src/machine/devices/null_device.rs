@@ -0,0 +1,15 @@
+use crate::machine::devices::device::Device;
+use std::any::Any;
+
+/// Always ready, reads 0 and drops writes.
+pub struct NullDevice {}
+
+impl Device for NullDevice {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn test(&mut self) -> bool { true }
+
+    fn read(&mut self) -> u8 { 0 }
+
+    fn write(&mut self, _val: u8) {}
+}
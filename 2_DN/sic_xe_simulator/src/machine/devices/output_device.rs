@@ -1,4 +1,4 @@
-use crate::machine::devices::device::Device;
+use crate::machine::{devices::device::Device, snapshot::DeviceState};
 use std::{any::Any, io::{self, Write}};
 
 pub struct OutputDevice {
@@ -8,7 +8,7 @@ pub struct OutputDevice {
 impl Device for OutputDevice {
     fn as_any(&self) -> &dyn Any { self }
 
-    fn test(&self) -> bool { true }
+    fn test(&mut self) -> bool { true }
 
     fn read(&mut self) -> u8 { 0 }
 
@@ -17,4 +17,14 @@ impl Device for OutputDevice {
         // NOTE: use the write_buffer if using the ratatui ui, if not use the normal printing to stdout
         self.write_buffer.push(val as char);
     }
+
+    fn save_state(&self) -> Option<DeviceState> {
+        Some(DeviceState::Output { text: self.write_buffer.clone() })
+    }
+
+    fn restore_state(&mut self, state: &DeviceState) -> bool {
+        let DeviceState::Output { text } = state else { return false };
+        self.write_buffer = text.clone();
+        true
+    }
 }
@@ -0,0 +1,295 @@
+use crate::machine::devices::device::{Device, Eof};
+use std::{
+    any::Any,
+    fs,
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::PathBuf,
+};
+#[cfg(unix)]
+use std::os::unix::{
+    fs::FileTypeExt,
+    net::{UnixListener, UnixStream},
+};
+
+/// bytes written and not sent yet, TD is not ready while there are this many
+const MAX_UNSENT: usize = 4096;
+
+/// Where a socket device connects to or listens on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Unix domain socket, only on Unix
+    Unix(PathBuf),
+    /// TCP port on 127.0.0.1
+    Tcp(u16),
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "{}", path.display()),
+            Endpoint::Tcp(port) => write!(f, "127.0.0.1:{port}"),
+        }
+    }
+}
+
+enum Listener {
+    #[cfg(unix)]
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+enum Stream {
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    fn set_nonblocking(&self) -> io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_nonblocking(true),
+            Stream::Tcp(stream) => stream.set_nonblocking(true),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+            Stream::Tcp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+            Stream::Tcp(stream) => stream.write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+            Stream::Tcp(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Byte stream to another process over a local socket, e.g. another simulator or a test driver.
+/// A listening device accepts one peer at a time, the next one after the peer closes.
+/// A connecting device retries until the peer listens. Written bytes are buffered and sent as
+/// the peer accepts them, also the ones written before there is a peer. TD is not ready while
+/// the buffer is full and bytes written then are lost.
+pub struct SocketDevice {
+    endpoint: Endpoint,
+    /// None -> connects to the endpoint
+    listener: Option<Listener>,
+    stream: Option<Stream>,
+    /// byte received by TD, not read yet
+    received: Option<u8>,
+    /// written, not accepted by the peer yet
+    unsent: Vec<u8>,
+    /// a connecting device's peer closed the connection
+    closed: bool,
+    eof: Eof,
+}
+
+impl SocketDevice {
+    /// Listen on the endpoint now, a stale Unix socket file is replaced.
+    pub fn listen(endpoint: Endpoint, eof: Eof) -> io::Result<Self> {
+        let listener = match &endpoint {
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                if fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                    fs::remove_file(path)?;
+                }
+                let listener = UnixListener::bind(path)?;
+                listener.set_nonblocking(true)?;
+                Listener::Unix(listener)
+            }
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => return Err(ErrorKind::Unsupported.into()),
+            Endpoint::Tcp(port) => {
+                let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, *port))?;
+                listener.set_nonblocking(true)?;
+                Listener::Tcp(listener)
+            }
+        };
+        let mut device = Self::connect(endpoint, eof);
+        device.listener = Some(listener);
+        Ok(device)
+    }
+
+    /// Connect on the first access.
+    pub fn connect(endpoint: Endpoint, eof: Eof) -> Self {
+        Self {
+            endpoint,
+            listener: None,
+            stream: None,
+            received: None,
+            unsent: Vec::new(),
+            closed: false,
+            eof,
+        }
+    }
+
+    /// accept or connect if there is no peer yet
+    fn connection(&mut self) -> Option<&mut Stream> {
+        if self.stream.is_none() && !self.closed {
+            let stream = match (&self.listener, &self.endpoint) {
+                #[cfg(unix)]
+                (Some(Listener::Unix(listener)), _) => {
+                    listener.accept().map(|(stream, _)| Stream::Unix(stream))
+                }
+                (Some(Listener::Tcp(listener)), _) => {
+                    listener.accept().map(|(stream, _)| Stream::Tcp(stream))
+                }
+                #[cfg(unix)]
+                (None, Endpoint::Unix(path)) => UnixStream::connect(path).map(Stream::Unix),
+                #[cfg(not(unix))]
+                (None, Endpoint::Unix(_)) => Err(ErrorKind::Unsupported.into()),
+                (None, Endpoint::Tcp(port)) => {
+                    TcpStream::connect((Ipv4Addr::LOCALHOST, *port)).map(Stream::Tcp)
+                }
+            };
+            self.stream = stream.and_then(|stream| stream.set_nonblocking().map(|_| stream)).ok();
+        }
+        self.stream.as_mut()
+    }
+
+    /// the peer is gone, a listening device waits for the next one
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.closed = self.listener.is_none();
+    }
+
+    /// send what the peer accepts of the buffered bytes, without waiting for it
+    fn flush(&mut self) {
+        if self.unsent.is_empty() || self.connection().is_none() {
+            return;
+        }
+        let Some(stream) = self.stream.as_mut() else { return };
+        while !self.unsent.is_empty() {
+            match stream.write(&self.unsent) {
+                Ok(0) => return self.disconnect(),
+                Ok(sent) => drop(self.unsent.drain(..sent)),
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                Err(_) => return self.disconnect(),
+            }
+        }
+    }
+
+    /// a received byte is ready in self.received
+    fn receive(&mut self) -> bool {
+        if self.received.is_some() {
+            return true;
+        }
+        let Some(stream) = self.connection() else { return false };
+        let mut buf = [0u8; 1];
+        match stream.read(&mut buf) {
+            Ok(1) => self.received = Some(buf[0]),
+            Err(error)
+                if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+            _ => self.disconnect(),
+        }
+        self.received.is_some()
+    }
+}
+
+impl Device for SocketDevice {
+    fn as_any(&self) -> &dyn Any { self }
+
+    /// data has been received and can be read, and there is room to write
+    fn test(&mut self) -> bool {
+        self.flush();
+        self.unsent.len() < MAX_UNSENT && self.receive()
+    }
+
+    fn is_waiting(&mut self) -> bool { self.eof == Eof::Wait && !self.receive() }
+
+    fn read(&mut self) -> u8 {
+        self.receive();
+        match (self.received.take(), self.eof) {
+            (Some(byte), _) => byte,
+            (None, Eof::Byte(byte)) => byte,
+            (None, Eof::Wait) => 0,
+        }
+    }
+
+    fn write(&mut self, val: u8) {
+        if self.unsent.len() < MAX_UNSENT {
+            self.unsent.push(val);
+        }
+        self.flush();
+    }
+
+    /// keeps sending the buffered bytes while the program runs
+    fn is_clocked(&self) -> bool { true }
+    fn tick(&mut self, _steps: u64, _cycles: u64) -> bool {
+        if self.stream.is_some() {
+            self.flush();
+        }
+        false
+    }
+}
+
+impl Drop for SocketDevice {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let (Some(Listener::Unix(_)), Endpoint::Unix(path)) = (&self.listener, &self.endpoint) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::temp_file::TempFile;
+
+    fn listening(file: &TempFile) -> (SocketDevice, UnixStream) {
+        let path = PathBuf::from(file.path());
+        let device = SocketDevice::listen(Endpoint::Unix(path.clone()), Eof::Wait).unwrap();
+        let peer = UnixStream::connect(path).unwrap();
+        (device, peer)
+    }
+
+    #[test]
+    fn receives_and_sends() {
+        let file = TempFile::new("socket_echo");
+        let (mut device, mut peer) = listening(&file);
+        assert!(!device.test());
+        peer.write_all(b"A").unwrap();
+        assert!(device.test());
+        assert_eq!(device.read(), b'A');
+        device.write(b'B');
+        let mut buf = [0u8; 1];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"B");
+    }
+
+    #[test]
+    fn peer_that_stops_reading_makes_td_not_ready() {
+        let file = TempFile::new("socket_stalled");
+        let (mut device, mut peer) = listening(&file);
+        peer.write_all(b"A").unwrap();
+        // fills the socket buffers and then MAX_UNSENT, without blocking
+        while device.unsent.len() < MAX_UNSENT {
+            device.write(0x55);
+        }
+        assert!(!device.test());
+
+        peer.set_nonblocking(true).unwrap();
+        let mut buf = vec![0u8; 1 << 16];
+        while peer.read(&mut buf).is_ok() {}
+        assert!(device.test());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::machine::devices::device::{Device, Eof};
+use std::{
+    any::Any,
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+/// first error writing the stream, shared with whoever reports it
+pub type WriteError = Arc<Mutex<Option<String>>>;
+
+/// Reads from and writes to host streams, e.g. stdin/stdout or files of a headless run.
+pub struct StreamDevice {
+    /// None -> reads 0
+    reader: Option<BufReader<Box<dyn Read + Send>>>,
+    /// None -> writes are dropped, also after the first error
+    writer: Option<Box<dyn Write + Send>>,
+    eof: Eof,
+    error: WriteError,
+}
+
+impl StreamDevice {
+    pub fn new(
+        reader: Option<Box<dyn Read + Send>>,
+        writer: Option<Box<dyn Write + Send>>,
+    ) -> Self {
+        Self {
+            reader: reader.map(BufReader::new),
+            writer,
+            eof: Eof::Byte(0),
+            error: Arc::new(Mutex::new(None)),
+        }
+    }
+    pub fn with_eof(mut self, eof: Eof) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    pub fn get_error(&self) -> WriteError { Arc::clone(&self.error) }
+
+    /// keeps the first error and stops writing
+    fn check(&mut self, result: io::Result<()>) {
+        if let Err(error) = result {
+            self.writer = None;
+            self.error.lock().unwrap().get_or_insert(error.to_string());
+        }
+    }
+}
+
+impl Device for StreamDevice {
+    fn as_any(&self) -> &dyn Any { self }
+
+    /// input is ready while there is a byte to read (blocks on an interactive stdin)
+    fn test(&mut self) -> bool {
+        match self.reader.as_mut() {
+            Some(reader) => reader.fill_buf().map(|buf| !buf.is_empty()).unwrap_or(false),
+            None => true,
+        }
+    }
+
+    fn is_waiting(&mut self) -> bool {
+        self.eof == Eof::Wait && self.reader.is_some() && !self.test()
+    }
+
+    fn read(&mut self) -> u8 {
+        let Some(reader) = self.reader.as_mut() else { return 0 };
+        let mut buf = [0u8; 1];
+        match (reader.read(&mut buf), self.eof) {
+            (Ok(1), _) => buf[0],
+            (_, Eof::Byte(byte)) => byte,
+            (_, Eof::Wait) => 0,
+        }
+    }
+
+    fn write(&mut self, val: u8) {
+        let Some(writer) = self.writer.as_mut() else { return };
+        let mut result = writer.write_all(&[val]);
+        if result.is_ok() && val == b'\n' {
+            result = writer.flush();
+        }
+        self.check(result);
+    }
+}
+
+impl Drop for StreamDevice {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let result = writer.flush();
+            self.check(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a pipe whose reader went away after `room` bytes
+    struct Closed {
+        room: usize,
+        written: Arc<Mutex<usize>>,
+    }
+
+    impl Write for Closed {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.room == 0 {
+                return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+            }
+            self.room -= 1;
+            *self.written.lock().unwrap() += 1;
+            Ok(buf.len().min(1))
+        }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn keeps_the_first_write_error() {
+        let written = Arc::new(Mutex::new(0));
+        let closed = Closed { room: 1, written: Arc::clone(&written) };
+        let mut device = StreamDevice::new(None, Some(Box::new(closed)));
+        let error = device.get_error();
+        for val in b"abc" {
+            device.write(*val);
+        }
+        drop(device);
+        assert_eq!(*written.lock().unwrap(), 1);
+        let expected = io::Error::from(io::ErrorKind::BrokenPipe).to_string();
+        assert_eq!(error.lock().unwrap().as_deref(), Some(expected.as_str()));
+    }
+}
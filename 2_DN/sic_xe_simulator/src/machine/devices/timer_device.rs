@@ -0,0 +1,118 @@
+use crate::machine::{devices::device::Device, snapshot::DeviceState};
+use std::any::Any;
+
+/// Programmable interval timer: raises the timer interrupt (class III) every `interval`
+/// cycles (see timing), like a repeating STI.
+///
+/// WD: the interval is written as a word, most significant byte first, the third byte starts
+/// the timer (0 stops it). RD: the cycles left, as a word, in the same byte order.
+pub struct TimerDevice {
+    interval: u32,
+    /// cycles left, 0 -> stopped
+    remaining: u32,
+    /// interval bytes written so far
+    written: Vec<u8>,
+    /// next byte of the remaining count to read
+    read_index: usize,
+}
+
+impl TimerDevice {
+    pub fn new() -> Self { Self { interval: 0, remaining: 0, written: Vec::new(), read_index: 0 } }
+
+    /// started with an interval, without a program
+    pub fn with_interval(interval: u32) -> Self {
+        Self { interval, remaining: interval, ..Self::new() }
+    }
+}
+
+impl Device for TimerDevice {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn test(&mut self) -> bool { true }
+
+    fn read(&mut self) -> u8 {
+        let byte = (self.remaining >> (8 * (2 - self.read_index))) as u8;
+        self.read_index = (self.read_index + 1) % 3;
+        byte
+    }
+
+    fn write(&mut self, val: u8) {
+        self.written.push(val);
+        if self.written.len() == 3 {
+            let interval = self.written.drain(..).fold(0, |word, byte| word << 8 | byte as u32);
+            self.interval = interval;
+            self.remaining = interval;
+            self.read_index = 0;
+        }
+    }
+
+    fn is_clocked(&self) -> bool { true }
+
+    fn tick(&mut self, _steps: u64, cycles: u64) -> bool {
+        if self.remaining == 0 {
+            return false;
+        }
+        if cycles < self.remaining as u64 {
+            self.remaining -= cycles as u32;
+            return false;
+        }
+        // the cycles past the interrupt count towards the next one
+        let interval = self.interval.max(1) as u64;
+        let past = (cycles - self.remaining as u64) % interval;
+        self.remaining = (interval - past) as u32;
+        true
+    }
+
+    fn is_armed(&self) -> bool { self.remaining > 0 }
+
+    fn save_state(&self) -> Option<DeviceState> {
+        Some(DeviceState::Timer {
+            interval: self.interval,
+            remaining: self.remaining,
+            written: self.written.clone(),
+            read_index: self.read_index,
+        })
+    }
+
+    fn restore_state(&mut self, state: &DeviceState) -> bool {
+        let DeviceState::Timer { interval, remaining, written, read_index } = state else {
+            return false;
+        };
+        self.interval = *interval;
+        self.remaining = *remaining;
+        self.written = written.clone();
+        self.read_index = *read_index % 3;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupts_every_interval_cycles() {
+        let mut timer = TimerDevice::new();
+        assert!(!timer.is_armed());
+        for byte in [0x00, 0x00, 0x0A] {
+            timer.write(byte);
+        }
+        assert!(!timer.tick(1, 9));
+        assert!(timer.tick(2, 4));
+        // 3 cycles past the interrupt count towards the next one
+        assert_eq!([timer.read(), timer.read(), timer.read()], [0x00, 0x00, 0x07]);
+        assert!(timer.tick(3, 25));
+        assert_eq!(timer.remaining, 2);
+    }
+
+    #[test]
+    fn zero_interval_stops_it() {
+        let mut timer = TimerDevice::with_interval(5);
+        assert!(timer.is_armed());
+        for _ in 0..3 {
+            timer.write(0);
+        }
+        assert!(!timer.is_armed());
+        assert!(!timer.tick(1, 100));
+    }
+}
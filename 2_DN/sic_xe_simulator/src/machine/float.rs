@@ -0,0 +1,266 @@
+use std::{cmp::Ordering, fmt};
+
+use crate::sic_xe::i32_to_i24;
+
+const EXPONENT_BIAS: i32 = 1024;
+const EXPONENT_MAX: i32 = 0x7FF;
+const FRACTION_BITS: i32 = 36;
+const FRACTION_MASK: u64 = (1 << FRACTION_BITS) - 1;
+const FRACTION_MSB: u64 = 1 << (FRACTION_BITS - 1);
+/// extra low bits of the fractions in add and div, so the result is truncated only once
+const GUARD_BITS: u32 = 64;
+
+/// SIC/XE 48b floating point number
+/// 1b,11b,36b == sign,exponent,fraction
+/// \   value = (-1)^sign * 0.fraction * 2^(exponent - 1024)
+/// \   zero  -> all bits 0
+///
+/// Fields are kept raw, so unnormalized values loaded from memory survive until NORM or
+/// an arithmetic instruction normalizes them. Arithmetic is done on the fractions and the exact
+/// result is truncated to 36b once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SicFloat {
+    sign: bool,
+    /// 11b, excess 1024
+    exponent: u16,
+    /// 36b, binary point before the msb
+    fraction: u64,
+}
+
+impl SicFloat {
+    pub fn zero() -> Self { Self::default() }
+
+    pub fn from_bytes(bytes: [u8; 6]) -> Self {
+        let val = bytes.iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+        Self {
+            sign: (val >> 47) & 1 != 0,
+            exponent: ((val >> FRACTION_BITS) & EXPONENT_MAX as u64) as u16,
+            fraction: val & FRACTION_MASK,
+        }
+    }
+    pub fn to_bytes(self) -> [u8; 6] {
+        let val = (self.sign as u64) << 47
+            | (self.exponent as u64) << FRACTION_BITS
+            | (self.fraction & FRACTION_MASK);
+        let bytes = val.to_be_bytes();
+        [bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]
+    }
+
+    /// converts host float, truncating the fraction to 36b
+    /// return:
+    /// \   None -> NaN, infinity or exponent overflow
+    pub fn from_f64(val: f64) -> Option<Self> {
+        if !val.is_finite() {
+            return None;
+        }
+        if val == 0.0 {
+            return Some(Self::zero());
+        }
+
+        // f64: value = 1.mantissa * 2^(exponent - 1023)
+        let raw = val.abs().to_bits();
+        let mut exponent = ((raw >> 52) & 0x7FF) as i32;
+        let mut mantissa = raw & ((1 << 52) - 1);
+        if exponent == 0 {
+            // subnormal, shift until the hidden bit is set
+            exponent = 1;
+            while mantissa & (1 << 52) == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+        } else {
+            mantissa |= 1 << 52;
+        }
+
+        // 1.mantissa * 2^(e - 1023) == 0.1mantissa * 2^(e - 1022)
+        let exponent = exponent - 1022 + EXPONENT_BIAS;
+        if exponent > EXPONENT_MAX {
+            return None;
+        }
+        if exponent < 0 {
+            // underflow
+            return Some(Self::zero());
+        }
+
+        Some(Self {
+            sign: val < 0.0,
+            exponent: exponent as u16,
+            fraction: mantissa >> (53 - FRACTION_BITS),
+        })
+    }
+    /// exact, since 36b fraction fits into the 53b f64 mantissa
+    pub fn to_f64(self) -> f64 {
+        if self.fraction == 0 {
+            return 0.0;
+        }
+        let magnitude = self.fraction as f64
+            * 2f64.powi(self.exponent as i32 - EXPONENT_BIAS - FRACTION_BITS);
+        if self.sign { -magnitude } else { magnitude }
+    }
+
+    /// FLOAT: i24 is always representable
+    pub fn from_i24(val: i32) -> Self {
+        Self::from_f64(val as f64).expect("i24 always fits into SIC/XE float")
+    }
+    /// FIX: truncates towards zero
+    pub fn to_i24(self) -> i32 { i32_to_i24(self.to_f64().trunc() as i32) }
+
+    pub fn is_zero(&self) -> bool { self.fraction == 0 }
+
+    /// NORM: shift fraction left until its msb is set
+    pub fn normalize(&self) -> Self {
+        if self.is_zero() {
+            return Self::zero();
+        }
+        let mut exponent = self.exponent as i32;
+        let mut fraction = self.fraction & FRACTION_MASK;
+        while fraction & FRACTION_MSB == 0 {
+            fraction <<= 1;
+            exponent -= 1;
+        }
+        if exponent < 0 {
+            return Self::zero();
+        }
+        Self { sign: self.sign, exponent: exponent as u16, fraction }
+    }
+
+    /// normalized value of mantissa * 2^scale, the fraction truncated to 36b
+    /// return:
+    /// \   None -> exponent overflow
+    fn from_parts(sign: bool, mantissa: u128, scale: i32) -> Option<Self> {
+        if mantissa == 0 {
+            return Some(Self::zero());
+        }
+        // mantissa * 2^scale == 0.mantissa * 2^(scale + bits)
+        let bits = (u128::BITS - mantissa.leading_zeros()) as i32;
+        let fraction = if bits > FRACTION_BITS {
+            mantissa >> (bits - FRACTION_BITS)
+        } else {
+            mantissa << (FRACTION_BITS - bits)
+        };
+        let exponent = scale + bits + EXPONENT_BIAS;
+        if exponent > EXPONENT_MAX {
+            return None;
+        }
+        if exponent < 0 {
+            // underflow
+            return Some(Self::zero());
+        }
+        Some(Self { sign, exponent: exponent as u16, fraction: fraction as u64 })
+    }
+    /// value == fraction * 2^scale
+    fn scale(&self) -> i32 { self.exponent as i32 - EXPONENT_BIAS - FRACTION_BITS }
+
+    // arithmetic
+    // return None on overflow (and division by zero)
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let (a, b) = (self.normalize(), other.normalize());
+        if b.is_zero() {
+            return Some(a);
+        }
+        if a.is_zero() {
+            return Some(b);
+        }
+        // a is the larger magnitude, b is aligned to it
+        let (a, b) =
+            if (a.exponent, a.fraction) >= (b.exponent, b.fraction) { (a, b) } else { (b, a) };
+        let shift = (a.exponent - b.exponent) as u32;
+        let aligned = (b.fraction as u128) << GUARD_BITS;
+        // bits shifted out only matter for truncation, one sticky bit stands for them
+        let small = match aligned.checked_shr(shift) {
+            Some(small) if small << shift == aligned => small,
+            Some(small) => small | 1,
+            None => 1,
+        };
+        let big = (a.fraction as u128) << GUARD_BITS;
+        let mantissa = if a.sign == b.sign { big + small } else { big - small };
+        Self::from_parts(a.sign, mantissa, a.scale() - GUARD_BITS as i32)
+    }
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        self.checked_add(&Self { sign: !other.sign, ..*other })
+    }
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let (a, b) = (self.normalize(), other.normalize());
+        if a.is_zero() || b.is_zero() {
+            return Some(Self::zero());
+        }
+        let mantissa = a.fraction as u128 * b.fraction as u128;
+        Self::from_parts(a.sign != b.sign, mantissa, a.scale() + b.scale())
+    }
+    pub fn checked_div(&self, other: &Self) -> Option<Self> {
+        let (a, b) = (self.normalize(), other.normalize());
+        if b.is_zero() {
+            return None;
+        }
+        if a.is_zero() {
+            return Some(Self::zero());
+        }
+        // at least 64b of quotient, truncating it again keeps the truncated exact quotient
+        let mantissa = ((a.fraction as u128) << GUARD_BITS) / b.fraction as u128;
+        Self::from_parts(a.sign != b.sign, mantissa, a.scale() - GUARD_BITS as i32 - b.scale())
+    }
+
+    /// COMPF: compares values, so unnormalized and normalized forms are equal
+    pub fn compare(&self, other: &Self) -> Ordering { self.to_f64().total_cmp(&other.to_f64()) }
+}
+
+impl fmt::Display for SicFloat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.to_f64()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float(val: f64) -> SicFloat { SicFloat::from_f64(val).unwrap() }
+
+    #[test]
+    fn encodes_and_decodes() {
+        let one = float(1.0);
+        assert_eq!(one.to_bytes(), [0x40, 0x18, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(float(-2.5).to_bytes(), [0xC0, 0x2A, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(SicFloat::from_bytes(one.to_bytes()), one);
+        assert_eq!(float(0.1).to_f64(), (0.1 * (1u64 << 39) as f64).trunc() / (1u64 << 39) as f64);
+        assert_eq!(SicFloat::from_f64(f64::NAN), None);
+        assert_eq!(SicFloat::from_f64(f64::MAX), None);
+    }
+
+    #[test]
+    fn fix_and_float() {
+        assert_eq!(float(-3.75).to_i24(), -3);
+        assert_eq!(SicFloat::from_i24(-0x800000).to_f64(), -8388608.0);
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(float(1.5).checked_add(&float(2.25)), Some(float(3.75)));
+        assert_eq!(float(1.5).checked_add(&float(-2.25)), Some(float(-0.75)));
+        assert_eq!(float(1.0).checked_sub(&float(1.0)), Some(SicFloat::zero()));
+        assert_eq!(float(3.0).checked_mul(&float(-0.5)), Some(float(-1.5)));
+        assert_eq!(float(1.0).checked_div(&float(3.0)), Some(float(1.0 / 3.0)));
+        assert_eq!(float(1.0).checked_div(&SicFloat::zero()), None);
+        assert_eq!(float(2f64.powi(1000)).checked_mul(&float(2f64.powi(1000))), None);
+    }
+
+    #[test]
+    fn operands_are_normalized() {
+        // 0.000...01 * 2^36 == 1
+        let one = SicFloat { sign: false, exponent: (EXPONENT_BIAS + 36) as u16, fraction: 1 };
+        assert_eq!(one.normalize(), float(1.0));
+        assert_eq!(one.checked_add(&SicFloat::zero()), Some(float(1.0)));
+        assert_eq!(one.compare(&float(1.0)), Ordering::Equal);
+    }
+
+    #[test]
+    fn exact_result_is_truncated_once() {
+        // 1 - 2^-80 rounds to 1 in f64, truncating it keeps all ones
+        let below_one =
+            SicFloat { sign: false, exponent: EXPONENT_BIAS as u16, fraction: FRACTION_MASK };
+        let tiny = float(2f64.powi(-80));
+        assert_eq!(float(1.0).checked_sub(&tiny), Some(below_one));
+        assert_eq!(float(-1.0).checked_add(&tiny), Some(SicFloat { sign: true, ..below_one }));
+        // (1 - 2^-36)^2 == 1 - 2^-35 + 2^-72
+        let squared = below_one.checked_mul(&below_one).unwrap();
+        assert_eq!(squared.fraction, FRACTION_MASK - 1);
+    }
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+
+use crate::{machine::Machine, sic_xe::u8arr_to_i24};
+
+// interrupt work area (offsets)
+// 00 new SW, 03 new PC, 06 old status (see STATUS_*)
+const WORK_AREA_NEW_SW: usize = 0x00;
+const WORK_AREA_NEW_PC: usize = 0x03;
+const WORK_AREA_OLD_STATUS: usize = 0x06;
+
+// processor status block, as stored on interrupt and loaded by LPS (offsets)
+// SW, PC, A, X, L, B, S, T: 3B each, F: 6B
+const STATUS_SW: usize = 0x00;
+const STATUS_PC: usize = 0x03;
+const STATUS_A: usize = 0x06;
+const STATUS_X: usize = 0x09;
+const STATUS_L: usize = 0x0C;
+const STATUS_B: usize = 0x0F;
+const STATUS_S: usize = 0x12;
+const STATUS_T: usize = 0x15;
+const STATUS_F: usize = 0x18;
+/// bytes of the processor status block
+pub const STATUS_LEN: usize = 0x1E;
+
+// program interrupt ICODEs
+const ICODE_ILLEGAL_INSTRUCTION: u8 = 0x00;
+const ICODE_PRIVILEGED_INSTRUCTION: u8 = 0x01;
+const ICODE_ADDRESS_OUT_OF_RANGE: u8 = 0x02;
+const ICODE_MEMORY_PROTECTION: u8 = 0x03;
+const ICODE_ARITHMETIC_OVERFLOW: u8 = 0x04;
+
+/// Cause of a program interrupt (class II)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramCheck {
+    /// first byte of the instruction
+    IllegalInstruction(u8),
+    /// mnemonic of an instruction the simulator doesn't model
+    NotImplemented(&'static str),
+    PrivilegedInstruction,
+    AddressOutOfRange(usize),
+    InvalidDevice(usize),
+    MemoryProtection(usize),
+    DivisionByZero,
+    FloatOverflow,
+}
+
+impl ProgramCheck {
+    pub fn icode(&self) -> u8 {
+        match self {
+            ProgramCheck::IllegalInstruction(_) | ProgramCheck::NotImplemented(_) => {
+                ICODE_ILLEGAL_INSTRUCTION
+            }
+            ProgramCheck::PrivilegedInstruction => ICODE_PRIVILEGED_INSTRUCTION,
+            ProgramCheck::AddressOutOfRange(_) | ProgramCheck::InvalidDevice(_) => {
+                ICODE_ADDRESS_OUT_OF_RANGE
+            }
+            ProgramCheck::MemoryProtection(_) => ICODE_MEMORY_PROTECTION,
+            ProgramCheck::DivisionByZero | ProgramCheck::FloatOverflow => ICODE_ARITHMETIC_OVERFLOW,
+        }
+    }
+}
+
+impl fmt::Display for ProgramCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramCheck::IllegalInstruction(byte) => write!(f, "illegal instruction {byte:02X}"),
+            ProgramCheck::NotImplemented(mnemonic) => write!(f, "{mnemonic} not implemented"),
+            ProgramCheck::PrivilegedInstruction => write!(f, "privileged instruction"),
+            ProgramCheck::AddressOutOfRange(address) => {
+                write!(f, "address {address:X} out of range")
+            }
+            ProgramCheck::InvalidDevice(device) => write!(f, "invalid device {device:X}"),
+            ProgramCheck::MemoryProtection(address) => {
+                write!(f, "memory protection violation at {address:06X}")
+            }
+            ProgramCheck::DivisionByZero => write!(f, "division by zero"),
+            ProgramCheck::FloatOverflow => write!(f, "floating point overflow"),
+        }
+    }
+}
+
+/// Ordered by priority, I is the highest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptClass {
+    /// I: SVC n, ICODE == n
+    Svc = 0,
+    /// II: program check, ICODE == ICODE_*
+    Program = 1,
+    /// III: interval timer ran out (STI)
+    Timer = 2,
+    /// IV: I/O, ICODE == channel
+    Io = 3,
+}
+
+impl InterruptClass {
+    const ALL: [InterruptClass; 4] =
+        [InterruptClass::Svc, InterruptClass::Program, InterruptClass::Timer, InterruptClass::Io];
+
+    pub fn work_area(&self) -> usize {
+        match self {
+            InterruptClass::Svc => 0x100,
+            InterruptClass::Program => 0x130,
+            InterruptClass::Timer => 0x160,
+            InterruptClass::Io => 0x190,
+        }
+    }
+
+    /// SVC and program interrupts are caused by the running instruction and can't be masked
+    fn is_maskable(&self) -> bool {
+        matches!(self, InterruptClass::Timer | InterruptClass::Io)
+    }
+    /// MASK bit of class I is the msb of the field
+    fn mask_bit(&self) -> i32 { 0x8000 >> *self as i32 }
+}
+
+pub struct Interrupts {
+    /// pending ICODE per class
+    pending: [Option<u8>; 4],
+    /// cause of the pending program interrupt
+    program_check: Option<ProgramCheck>,
+    /// STI: instructions left until a timer interrupt, 0 -> off
+    interval_timer: i32,
+}
+
+impl Interrupts {
+    pub fn new() -> Self { Self { pending: [None; 4], program_check: None, interval_timer: 0 } }
+
+    pub fn raise(&mut self, class: InterruptClass, icode: u8) {
+        self.pending[class as usize] = Some(icode);
+    }
+    pub fn raise_program_check(&mut self, cause: ProgramCheck) {
+        self.raise(InterruptClass::Program, cause.icode());
+        self.program_check = Some(cause);
+    }
+    /// withdraw the pending program interrupt, when nothing can handle it
+    pub fn take_program_check(&mut self) -> Option<ProgramCheck> {
+        self.pending[InterruptClass::Program as usize] = None;
+        self.program_check.take()
+    }
+    pub fn is_pending(&self, class: InterruptClass) -> bool {
+        self.pending[class as usize].is_some()
+    }
+    pub fn has_pending(&self) -> bool { self.pending.iter().any(Option::is_some) }
+    /// pending ICODE per class, I..IV
+    pub fn get_pending(&self) -> [Option<u8>; 4] { self.pending }
+    pub fn set_pending(&mut self, pending: [Option<u8>; 4]) {
+        self.pending = pending;
+        self.program_check = None;
+    }
+
+    pub fn get_interval_timer(&self) -> i32 { self.interval_timer }
+    pub fn set_interval_timer(&mut self, val: i32) { self.interval_timer = val.max(0); }
+
+    /// count down one instruction and raise the timer interrupt when it runs out
+    pub fn tick(&mut self) {
+        if self.interval_timer > 0 {
+            self.interval_timer -= 1;
+            if self.interval_timer == 0 {
+                self.raise(InterruptClass::Timer, 0);
+            }
+        }
+    }
+}
+
+impl Machine {
+    /// Take the highest priority pending interrupt that is not masked.
+    /// return:
+    /// \   Some -> interrupt taken, status switched to its work area
+    /// \   None -> nothing to do
+    pub fn service_interrupts(&mut self) -> Option<InterruptClass> {
+        let sw = self.registers.get_sw();
+        let class = InterruptClass::ALL.into_iter().find(|class| {
+            let enabled = !class.is_maskable() || sw & class.mask_bit() != 0;
+            self.interrupts.is_pending(*class) && enabled
+        })?;
+        let icode = self.interrupts.pending[class as usize].take().unwrap_or(0);
+        if class == InterruptClass::Program {
+            self.interrupts.program_check = None;
+        }
+
+        let work_area = class.work_area();
+        self.registers.set_icode(icode);
+        self.store_status(work_area + WORK_AREA_OLD_STATUS);
+
+        let new_sw = u8arr_to_i24(self.memory.get_word(work_area + WORK_AREA_NEW_SW));
+        let new_pc = u8arr_to_i24(self.memory.get_word(work_area + WORK_AREA_NEW_PC));
+        self.registers.set_sw(new_sw);
+        self.registers.set_icode(icode);
+        self.registers.set_pc(new_pc);
+
+        Some(class)
+    }
+
+    /// store processor status block at address
+    /// return: false -> the block is out of memory, nothing stored
+    pub fn store_status(&mut self, address: usize) -> bool {
+        if !self.memory.is_valid(address, STATUS_LEN) {
+            return false;
+        }
+        let registers = &self.registers;
+        self.memory.set_word(address + STATUS_SW, registers.get_sw_as_bytes());
+        self.memory.set_word(address + STATUS_PC, registers.get_pc_as_bytes());
+        self.memory.set_word(address + STATUS_A, registers.get_a_as_bytes());
+        self.memory.set_word(address + STATUS_X, registers.get_x_as_bytes());
+        self.memory.set_word(address + STATUS_L, registers.get_l_as_bytes());
+        self.memory.set_word(address + STATUS_B, registers.get_b_as_bytes());
+        self.memory.set_word(address + STATUS_S, registers.get_s_as_bytes());
+        self.memory.set_word(address + STATUS_T, registers.get_t_as_bytes());
+        self.memory.set_float(address + STATUS_F, registers.get_f_as_bytes());
+        true
+    }
+
+    /// LPS: load processor status block from address
+    /// return: false -> the block is out of memory, nothing loaded
+    pub fn load_status(&mut self, address: usize) -> bool {
+        if !self.memory.is_valid(address, STATUS_LEN) {
+            return false;
+        }
+        let memory = &self.memory;
+        let registers = &mut self.registers;
+        registers.set_sw_as_bytes(memory.get_word(address + STATUS_SW));
+        registers.set_pc_as_bytes(memory.get_word(address + STATUS_PC));
+        registers.set_a_as_bytes(memory.get_word(address + STATUS_A));
+        registers.set_x_as_bytes(memory.get_word(address + STATUS_X));
+        registers.set_l_as_bytes(memory.get_word(address + STATUS_L));
+        registers.set_b_as_bytes(memory.get_word(address + STATUS_B));
+        registers.set_s_as_bytes(memory.get_word(address + STATUS_S));
+        registers.set_t_as_bytes(memory.get_word(address + STATUS_T));
+        registers.set_f_as_bytes(memory.get_float(address + STATUS_F));
+        true
+    }
+
+    /// user mode may only write blocks with key 0 or its own process ID
+    pub fn can_write(&self, address: usize, len: usize) -> bool {
+        if self.registers.is_supervisor() {
+            return true;
+        }
+        let id = self.registers.get_id();
+        (address..address + len).all(|address| {
+            let key = self.memory.get_key(address);
+            key == 0 || key == id
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::memory::SIZE;
+
+    #[test]
+    fn status_block_past_the_end_of_memory_is_refused() {
+        let mut machine = Machine::new();
+        let last = SIZE - STATUS_LEN;
+        assert!(machine.store_status(last));
+        assert!(machine.load_status(last));
+        assert!(!machine.store_status(last + 1));
+        assert!(!machine.load_status(last + 1));
+    }
+
+    #[test]
+    fn takes_the_highest_priority_interrupt_that_is_not_masked() {
+        let mut machine = Machine::new();
+        machine.memory.set_word(InterruptClass::Timer.work_area() + WORK_AREA_NEW_PC, [0, 0x20, 0]);
+        machine.registers.set_sw(0);
+        machine.registers.set_pc(0x42);
+        machine.interrupts.raise(InterruptClass::Io, 1);
+        machine.interrupts.raise(InterruptClass::Timer, 0);
+        assert_eq!(machine.service_interrupts(), None);
+
+        machine.registers.set_sw(InterruptClass::Timer.mask_bit() | InterruptClass::Io.mask_bit());
+        assert_eq!(machine.service_interrupts(), Some(InterruptClass::Timer));
+        assert_eq!(machine.registers.get_pc(), 0x2000);
+        let old_status = InterruptClass::Timer.work_area() + WORK_AREA_OLD_STATUS;
+        assert_eq!(machine.memory.get_word(old_status + STATUS_PC), [0, 0, 0x42]);
+        assert!(machine.interrupts.is_pending(InterruptClass::Io));
+    }
+
+    #[test]
+    fn interval_timer_raises_when_it_runs_out() {
+        let mut interrupts = Interrupts::new();
+        interrupts.set_interval_timer(2);
+        interrupts.tick();
+        assert!(!interrupts.is_pending(InterruptClass::Timer));
+        interrupts.tick();
+        assert!(interrupts.is_pending(InterruptClass::Timer));
+        assert_eq!(interrupts.get_interval_timer(), 0);
+    }
+
+    #[test]
+    fn user_mode_writes_only_its_own_keys() {
+        let mut machine = Machine::new();
+        machine.registers.set_sw(0x0C0000);
+        machine.memory.set_key(0x1000, 3);
+        machine.memory.set_key(0x2000, 4);
+        assert!(machine.can_write(0x1000, 3));
+        assert!(!machine.can_write(0x2000, 3));
+        assert!(machine.can_write(0x3000, 3));
+    }
+}
@@ -1,29 +1,122 @@
-const MAX_ADDRESS: usize = 1 << 20 - 1;
+use crate::machine::{
+    decode_cache::DecodeCache,
+    watchpoints::{WatchKind, Watchpoints},
+};
+
+const MAX_ADDRESS: usize = (1 << 20) - 1;
 /// 1MB == 2^20B
-const SIZE: usize = MAX_ADDRESS + 1;
+pub const SIZE: usize = MAX_ADDRESS + 1;
+/// memory is protected (SSK) in blocks of 2KB
+pub const KEY_BLOCK_SIZE: usize = 0x800;
 
 /// Size: 1MB == 2^20B
 pub struct Memory {
     memory: Vec<u8>,
+    /// storage protection key of each block, 0 -> unprotected
+    keys: Vec<u8>,
+    /// checked by note_read and note_write
+    pub watchpoints: Watchpoints,
+    /// (address, old byte) of every write since begin_journal, None -> not recording
+    journal: Option<Vec<(usize, u8)>>,
+    /// instructions decoded from memory, dropped when written
+    pub decode_cache: DecodeCache,
 }
 
 impl Memory {
-    pub fn new() -> Self { Self { memory: vec![0; SIZE] } }
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0; SIZE],
+            keys: vec![0; SIZE.div_ceil(KEY_BLOCK_SIZE)],
+            watchpoints: Watchpoints::new(),
+            journal: None,
+            decode_cache: DecodeCache::new(SIZE),
+        }
+    }
+
+    /// address..address + len is inside memory
+    pub fn is_valid(&self, address: usize, len: usize) -> bool {
+        address.checked_add(len).is_some_and(|end| end <= SIZE)
+    }
 
     pub fn get_byte(&self, address: usize) -> u8 { self.memory[address] }
-    pub fn set_byte(&mut self, address: usize, val: u8) -> () { self.memory[address] = val; }
+    pub fn set_byte(&mut self, address: usize, val: u8) {
+        self.record(address, 1);
+        self.memory[address] = val;
+    }
 
     pub fn get_word(&self, address: usize) -> [u8; 3] {
         self.memory[address..address + 3].try_into().unwrap()
     }
     pub fn set_word(&mut self, address: usize, val: [u8; 3]) -> () {
+        self.record(address, 3);
         self.memory[address..address + 3].copy_from_slice(&val);
     }
 
+    pub fn get_bytes(&self, address: usize, len: usize) -> &[u8] {
+        &self.memory[address..address + len]
+    }
+    pub fn set_bytes(&mut self, address: usize, val: &[u8]) {
+        self.record(address, val.len());
+        self.memory[address..address + val.len()].copy_from_slice(val);
+    }
+
     pub fn get_float(&self, address: usize) -> [u8; 6] {
         self.memory[address..address + 6].try_into().unwrap()
     }
     pub fn set_float(&mut self, address: usize, val: [u8; 6]) -> () {
+        self.record(address, 6);
         self.memory[address..address + 6].copy_from_slice(&val);
     }
+
+    // undo journal
+    pub fn begin_journal(&mut self) { self.journal = Some(Vec::new()); }
+    pub fn end_journal(&mut self) -> Vec<(usize, u8)> { self.journal.take().unwrap_or_default() }
+    /// every write comes through here, before it changes memory
+    fn record(&mut self, address: usize, len: usize) {
+        self.decode_cache.invalidate(address, len);
+        if let Some(journal) = self.journal.as_mut() {
+            for address in address..address + len {
+                journal.push((address, self.memory[address]));
+            }
+        }
+    }
+
+    /// instruction is about to read address..address + len
+    pub fn note_read(&mut self, address: usize, len: usize) {
+        let val = &self.memory[address..address + len];
+        self.watchpoints.note(address, WatchKind::Read, val, val);
+    }
+    /// instruction is about to write val at address
+    pub fn note_write(&mut self, address: usize, val: &[u8]) {
+        let old = &self.memory[address..address + val.len()];
+        self.watchpoints.note(address, WatchKind::Write, old, val);
+    }
+
+    /// all bytes and keys back to 0, watchpoints stay
+    pub fn clear(&mut self) {
+        self.memory.fill(0);
+        self.keys.fill(0);
+        self.decode_cache.clear();
+    }
+
+    pub fn get_key(&self, address: usize) -> u8 { self.keys[address / KEY_BLOCK_SIZE] }
+    /// address out of memory -> nothing is set
+    pub fn set_key(&mut self, address: usize, key: u8) {
+        if let Some(block) = self.keys.get_mut(address / KEY_BLOCK_SIZE) {
+            *block = key;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_past_the_end_of_memory_is_ignored() {
+        let mut memory = Memory::new();
+        memory.set_key(SIZE - 1, 3);
+        memory.set_key(SIZE + KEY_BLOCK_SIZE, 5);
+        assert_eq!(memory.get_key(SIZE - 1), 3);
+    }
 }
@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Opcode {
     // ***** SIC format, SIC/XE Format 3 and 4
 
@@ -167,4 +167,18 @@ impl Opcode {
             _ => return None,
         })
     }
+
+    /// 1 byte: opcode
+    pub fn is_format_1(self) -> bool {
+        use Opcode::*;
+        matches!(self, Float | Fix | Norm | Sio | Hio | Tio)
+    }
+    /// 2 bytes: opcode, r1 r2
+    pub fn is_format_2(self) -> bool {
+        use Opcode::*;
+        matches!(
+            self,
+            Addr | Subr | Mulr | Divr | Compr | Shiftl | Shiftr | Rmo | Clear | Tixr | Svc
+        )
+    }
 }
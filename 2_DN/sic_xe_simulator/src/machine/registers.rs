@@ -1,7 +1,30 @@
+use std::cmp::Ordering;
+
+use crate::machine::float::SicFloat;
 use crate::sic_xe::i24_to_u8arr;
 use crate::sic_xe::i32_to_i24;
 use crate::sic_xe::u8arr_to_i24;
 
+// SW fields (bit 0 == msb)
+// 1b,1b,4b,2b,4b,4b,8b == MODE,IDLE,ID,CC,MASK,unused,ICODE
+/// 1 -> supervisor, 0 -> user
+pub const SW_MODE: i32 = 0x800000;
+/// 1 -> idle, waiting for an interrupt
+pub const SW_IDLE: i32 = 0x400000;
+/// process identifier
+pub const SW_ID: i32 = 0x3C0000;
+/// condition code
+pub const SW_CC: i32 = 0x030000;
+/// interrupt mask, one bit per class (I..IV), 1 -> enabled
+pub const SW_MASK: i32 = 0x00F000;
+/// interruption code
+pub const SW_ICODE: i32 = 0x0000FF;
+
+const CC_EQUAL: i32 = 0x000000;
+const CC_LESS: i32 = 0x010000;
+const CC_GREATER: i32 = 0x020000;
+
+#[derive(Clone)]
 pub struct Registers {
     /// 24b
     a: i32,
@@ -16,18 +39,19 @@ pub struct Registers {
     /// 24b
     t: i32,
     /// 48b
-    f: f64,
+    f: SicFloat,
     /// 24b
     pc: i32,
     /// 24b
-    /// 0    .. =
-    /// 1    .. >
-    /// -1   .. <
+    /// see SW_* for fields
     sw: i32,
 }
 
 impl Registers {
-    pub fn new() -> Self { Self { a: 0, x: 0, l: 0, b: 0, s: 0, t: 0, f: 0.0, pc: 0, sw: 0 } }
+    #[rustfmt::skip]
+    pub fn new() -> Self {
+        Self { a: 0, x: 0, l: 0, b: 0, s: 0, t: 0, f: SicFloat::zero(), pc: 0, sw: SW_MODE }
+    }
 
     // Getters and setters
     pub fn get_a(&self) -> i32 { self.a }
@@ -36,7 +60,7 @@ impl Registers {
     pub fn get_b(&self) -> i32 { self.b }
     pub fn get_s(&self) -> i32 { self.s }
     pub fn get_t(&self) -> i32 { self.t }
-    pub fn get_f(&self) -> f64 { self.f }
+    pub fn get_f(&self) -> SicFloat { self.f }
     pub fn get_pc(&self) -> i32 { self.pc }
     pub fn get_sw(&self) -> i32 { self.sw }
     pub fn get_a_as_bytes(&self) -> [u8; 3] { i24_to_u8arr(self.a) }
@@ -45,7 +69,7 @@ impl Registers {
     pub fn get_b_as_bytes(&self) -> [u8; 3] { i24_to_u8arr(self.b) }
     pub fn get_s_as_bytes(&self) -> [u8; 3] { i24_to_u8arr(self.s) }
     pub fn get_t_as_bytes(&self) -> [u8; 3] { i24_to_u8arr(self.t) }
-    pub fn get_f_as_bytes(&self) -> f64 { self.f }
+    pub fn get_f_as_bytes(&self) -> [u8; 6] { self.f.to_bytes() }
     pub fn get_pc_as_bytes(&self) -> [u8; 3] { i24_to_u8arr(self.pc) }
     pub fn get_sw_as_bytes(&self) -> [u8; 3] { i24_to_u8arr(self.sw) }
 
@@ -55,7 +79,7 @@ impl Registers {
     pub fn set_b(&mut self, val: i32) -> () { self.b = i32_to_i24(val); }
     pub fn set_s(&mut self, val: i32) -> () { self.s = i32_to_i24(val); }
     pub fn set_t(&mut self, val: i32) -> () { self.t = i32_to_i24(val); }
-    pub fn set_f(&mut self, val: f64) -> () { self.f = val; }
+    pub fn set_f(&mut self, val: SicFloat) { self.f = val; }
     pub fn set_pc(&mut self, val: i32) -> () { self.pc = i32_to_i24(val); }
     pub fn set_sw(&mut self, val: i32) -> () { self.sw = i32_to_i24(val); }
     pub fn set_a_as_bytes(&mut self, val: [u8; 3]) -> () { self.a = u8arr_to_i24(val); }
@@ -64,9 +88,38 @@ impl Registers {
     pub fn set_b_as_bytes(&mut self, val: [u8; 3]) -> () { self.b = u8arr_to_i24(val); }
     pub fn set_s_as_bytes(&mut self, val: [u8; 3]) -> () { self.s = u8arr_to_i24(val); }
     pub fn set_t_as_bytes(&mut self, val: [u8; 3]) -> () { self.t = u8arr_to_i24(val); }
+    pub fn set_f_as_bytes(&mut self, val: [u8; 6]) { self.f = SicFloat::from_bytes(val); }
     pub fn set_pc_as_bytes(&mut self, val: [u8; 3]) -> () { self.pc = u8arr_to_i24(val); }
     pub fn set_sw_as_bytes(&mut self, val: [u8; 3]) -> () { self.sw = u8arr_to_i24(val); }
 
+    // SW fields
+    pub fn get_cc(&self) -> Ordering {
+        match self.sw & SW_CC {
+            CC_LESS => Ordering::Less,
+            CC_GREATER => Ordering::Greater,
+            _ => Ordering::Equal,
+        }
+    }
+    pub fn set_cc(&mut self, val: Ordering) {
+        let cc = match val {
+            Ordering::Less => CC_LESS,
+            Ordering::Equal => CC_EQUAL,
+            Ordering::Greater => CC_GREATER,
+        };
+        self.set_sw((self.sw & !SW_CC) | cc);
+    }
+    pub fn is_supervisor(&self) -> bool { self.sw & SW_MODE != 0 }
+    pub fn is_idle(&self) -> bool { self.sw & SW_IDLE != 0 }
+    pub fn get_id(&self) -> u8 { ((self.sw & SW_ID) >> 18) as u8 }
+    pub fn get_mask(&self) -> u8 { ((self.sw & SW_MASK) >> 12) as u8 }
+    pub fn get_icode(&self) -> u8 { (self.sw & SW_ICODE) as u8 }
+    pub fn set_icode(&mut self, val: u8) {
+        self.set_sw((self.sw & !SW_ICODE) | val as i32);
+    }
+
+    /// index is accepted by get_reg and set_reg
+    pub fn is_valid_index(index: usize) -> bool { matches!(index, 0..=5 | 8 | 9) }
+
     /// **UNSTABLE** TODO: Fails on index 6 for now
     /// Get register by index
     /// A .. 0
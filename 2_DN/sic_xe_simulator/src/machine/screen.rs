@@ -0,0 +1,70 @@
+use crate::machine::Machine;
+
+/// Memory-mapped text screen: one byte per cell, row after row.
+#[derive(Debug, Clone, Copy)]
+pub struct Screen {
+    pub address: usize,
+    pub cols: usize,
+    pub rows: usize,
+}
+
+impl Screen {
+    /// 0xB800, 80x25, as used by screen.asm
+    pub fn new() -> Self { Self { address: 0xB800, cols: 80, rows: 25 } }
+
+    /// None -> cols * rows overflows
+    pub fn len(&self) -> Option<usize> { self.cols.checked_mul(self.rows) }
+}
+
+impl Machine {
+    /// Show the screen, if it is not empty and fits in memory.
+    pub fn set_screen(&mut self, screen: Screen) -> Result<(), String> {
+        let len = screen.len().filter(|len| *len > 0).ok_or("Invalid screen size")?;
+        if !self.memory.is_valid(screen.address, len) {
+            return Err(format!("Screen at {:06X} does not fit in memory", screen.address));
+        }
+        self.screen = Some(screen);
+        Ok(())
+    }
+
+    /// Text of the screen, one string per row. Unprintable bytes show as spaces.
+    /// None -> no screen or it doesn't fit in memory
+    pub fn screen_lines(&self) -> Option<Vec<String>> {
+        let screen = self.screen?;
+        if !self.memory.is_valid(screen.address, screen.len()?) {
+            return None;
+        }
+        let lines = (0..screen.rows)
+            .map(|row| {
+                let start = screen.address + row * screen.cols;
+                (start..start + screen.cols)
+                    .map(|address| match self.memory.get_byte(address) {
+                        byte @ 0x20..=0x7E => byte as char,
+                        _ => ' ',
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::memory::SIZE;
+
+    #[test]
+    fn rejects_screens_that_overflow_or_leave_memory() {
+        let mut machine = Machine::new();
+        let huge = Screen { address: 0, cols: usize::MAX, rows: 2 };
+        assert_eq!(huge.len(), None);
+        assert!(machine.set_screen(huge).is_err());
+        assert!(machine.set_screen(Screen { address: SIZE - 79, cols: 80, rows: 1 }).is_err());
+        assert!(machine.set_screen(Screen { address: 0, cols: 0, rows: 25 }).is_err());
+
+        machine.set_screen(Screen { address: SIZE - 80, cols: 80, rows: 1 }).unwrap();
+        machine.memory.set_byte(SIZE - 80, b'A');
+        assert_eq!(machine.screen_lines().unwrap()[0].trim_end(), "A");
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::machine::{
+    float::SicFloat,
+    memory::{KEY_BLOCK_SIZE, SIZE},
+    screen::Screen,
+    Machine,
+};
+
+/// memory is saved in pages of this many bytes, pages of zeros are left out
+const PAGE_SIZE: usize = 0x100;
+
+/// State of a device, for the devices that can save theirs (see Device::save_state)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DeviceState {
+    /// <hex>.dev file: offset of the next read/write
+    File { position: u64, output: bool },
+    /// keyboard: bytes typed but not read yet
+    Keyboard { queue: Vec<u8> },
+    /// text shown in the Output pane
+    Output { text: String },
+    Buffer { input: Vec<u8>, written: String, reading: bool },
+    Timer { interval: u32, remaining: u32, written: Vec<u8>, read_index: usize },
+}
+
+impl DeviceState {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DeviceState::File { .. } => "file",
+            DeviceState::Keyboard { .. } => "keyboard",
+            DeviceState::Output { .. } => "output",
+            DeviceState::Buffer { .. } => "buffer",
+            DeviceState::Timer { .. } => "timer",
+        }
+    }
+}
+
+/// Registers, memory, interrupts, screen and device states of a Machine.
+/// Numbers that are addresses or device numbers are kept as hex strings, like in the UI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachineState {
+    a: i32,
+    x: i32,
+    l: i32,
+    b: i32,
+    s: i32,
+    t: i32,
+    /// 12 hex digits, the raw 48 bits
+    f: String,
+    pc: i32,
+    sw: i32,
+    /// page address -> bytes in hex, pages that aren't all zeros
+    memory: BTreeMap<String, String>,
+    /// block address -> protection key, keys that aren't 0
+    keys: BTreeMap<String, u8>,
+    interval_timer: i32,
+    /// pending ICODE per interrupt class, I..IV
+    pending_interrupts: [Option<u8>; 4],
+    /// address, cols, rows
+    screen: Option<(usize, usize, usize)>,
+    /// device number -> state
+    devices: BTreeMap<String, DeviceState>,
+}
+
+fn to_hex(bytes: &[u8]) -> String { bytes.iter().map(|byte| format!("{byte:02X}")).collect() }
+
+fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) {
+        return Err(format!("Odd number of hex digits in {text}"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            let digits = text.get(i..i + 2).ok_or_else(|| format!("Invalid hex {text}"))?;
+            u8::from_str_radix(digits, 16).map_err(|_| format!("Invalid hex {digits}"))
+        })
+        .collect()
+}
+
+fn parse_address(text: &str, what: &str) -> Result<usize, String> {
+    usize::from_str_radix(text, 16).map_err(|_| format!("Invalid {what} {text}"))
+}
+
+impl Machine {
+    pub fn save_state(&self) -> MachineState {
+        let registers = &self.registers;
+        let memory = (0..SIZE)
+            .step_by(PAGE_SIZE)
+            .map(|address| (address, self.memory.get_bytes(address, PAGE_SIZE)))
+            .filter(|(_, page)| page.iter().any(|byte| *byte != 0))
+            .map(|(address, page)| (format!("{address:06X}"), to_hex(page)))
+            .collect();
+        let keys = (0..SIZE)
+            .step_by(KEY_BLOCK_SIZE)
+            .map(|address| (address, self.memory.get_key(address)))
+            .filter(|(_, key)| *key != 0)
+            .map(|(address, key)| (format!("{address:06X}"), key))
+            .collect();
+        let devices = self
+            .devices
+            .iter()
+            .enumerate()
+            .filter_map(|(index, device)| Some((format!("{index:02X}"), device.save_state()?)))
+            .collect();
+
+        MachineState {
+            a: registers.get_a(),
+            x: registers.get_x(),
+            l: registers.get_l(),
+            b: registers.get_b(),
+            s: registers.get_s(),
+            t: registers.get_t(),
+            f: to_hex(&registers.get_f_as_bytes()),
+            pc: registers.get_pc(),
+            sw: registers.get_sw(),
+            memory,
+            keys,
+            interval_timer: self.interrupts.get_interval_timer(),
+            pending_interrupts: self.interrupts.get_pending(),
+            screen: self.screen.map(|screen| (screen.address, screen.cols, screen.rows)),
+            devices,
+        }
+    }
+
+    /// Replace the state with a saved one. Devices keep their backends, only their state is
+    /// restored. A device that can't take its saved state (e.g. it is mapped to another backend
+    /// now) keeps its own.
+    /// return:
+    /// \   Ok -> restored, with a problem for each device that kept its state
+    /// \   Err -> invalid state, nothing changed
+    pub fn restore_state(&mut self, state: &MachineState) -> Result<Vec<String>, String> {
+        // parse everything before changing anything
+        let f: [u8; 6] = from_hex(&state.f)?
+            .try_into()
+            .map_err(|_| format!("F must be 12 hex digits, not {}", state.f))?;
+        let mut pages = Vec::new();
+        for (address, bytes) in &state.memory {
+            let address = parse_address(address, "memory address")?;
+            let bytes = from_hex(bytes)?;
+            if !self.memory.is_valid(address, bytes.len()) {
+                return Err(format!("Memory page {address:06X} is out of range"));
+            }
+            pages.push((address, bytes));
+        }
+        let mut keys = Vec::new();
+        for (address, key) in &state.keys {
+            let address = parse_address(address, "key block address")?;
+            if !self.memory.is_valid(address, 1) {
+                return Err(format!("Key block {address:06X} is out of range"));
+            }
+            keys.push((address, *key));
+        }
+        let mut devices = Vec::new();
+        for (number, device_state) in &state.devices {
+            let index = parse_address(number, "device number")?;
+            if !self.has_device(index) {
+                return Err(format!("Invalid device number {number}"));
+            }
+            devices.push((index, device_state));
+        }
+
+        let registers = &mut self.registers;
+        registers.set_a(state.a);
+        registers.set_x(state.x);
+        registers.set_l(state.l);
+        registers.set_b(state.b);
+        registers.set_s(state.s);
+        registers.set_t(state.t);
+        registers.set_f(SicFloat::from_bytes(f));
+        registers.set_pc(state.pc);
+        registers.set_sw(state.sw);
+
+        self.memory.clear();
+        for (address, bytes) in pages {
+            self.memory.set_bytes(address, &bytes);
+        }
+        for (address, key) in keys {
+            self.memory.set_key(address, key);
+        }
+        self.interrupts.set_interval_timer(state.interval_timer);
+        self.interrupts.set_pending(state.pending_interrupts);
+        self.screen = state.screen.map(|(address, cols, rows)| Screen { address, cols, rows });
+
+        let problems = devices
+            .into_iter()
+            .filter(|(index, device_state)| !self.devices[*index].restore_state(device_state))
+            .map(|(index, device_state)| {
+                format!("Device {index:X} can't restore its {} state", device_state.kind())
+            })
+            .collect();
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{devices::timer_device::TimerDevice, interrupts::InterruptClass};
+
+    /// through JSON, like a snapshot file
+    fn saved(machine: &Machine) -> MachineState {
+        let text = serde_json::to_string(&machine.save_state()).unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn restores_what_was_saved() {
+        let mut machine = Machine::new();
+        machine.registers.set_a(0x123456);
+        machine.registers.set_f_as_bytes([0x40, 0x18, 0, 0, 0, 1]);
+        machine.registers.set_pc(0x1000);
+        machine.memory.set_word(0x1000, [1, 2, 3]);
+        machine.memory.set_key(0x1800, 5);
+        machine.interrupts.set_interval_timer(9);
+        machine.interrupts.raise(InterruptClass::Io, 2);
+        machine.input_queue(0).unwrap().lock().unwrap().push_back(b'k');
+        machine.set_device(0xF6, Box::new(TimerDevice::with_interval(100)));
+        let state = saved(&machine);
+
+        let mut restored = Machine::new();
+        restored.set_device(0xF6, Box::new(TimerDevice::new()));
+        assert_eq!(restored.restore_state(&state), Ok(vec![]));
+        assert_eq!(restored.registers.get_a(), 0x123456);
+        assert_eq!(restored.registers.get_f_as_bytes(), [0x40, 0x18, 0, 0, 0, 1]);
+        assert_eq!(restored.registers.get_pc(), 0x1000);
+        assert_eq!(restored.memory.get_word(0x1000), [1, 2, 3]);
+        assert_eq!(restored.memory.get_key(0x1800), 5);
+        assert_eq!(restored.interrupts.get_interval_timer(), 9);
+        assert_eq!(restored.interrupts.get_pending()[InterruptClass::Io as usize], Some(2));
+        assert_eq!(restored.get_device(0).read(), b'k');
+        assert!(restored.has_armed_timer());
+    }
+
+    #[test]
+    fn reports_devices_that_keep_their_state() {
+        let mut machine = Machine::new();
+        machine.set_device(0xF6, Box::new(TimerDevice::with_interval(100)));
+        let state = saved(&machine);
+        let problems = Machine::new().restore_state(&state).unwrap();
+        assert_eq!(problems, ["Device F6 can't restore its timer state"]);
+    }
+
+    #[test]
+    fn invalid_state_changes_nothing() {
+        let mut state = saved(&Machine::new());
+        state.a = 7;
+        state.memory.insert("0FFFFF".to_string(), "0000".to_string());
+        let mut machine = Machine::new();
+        assert_eq!(machine.restore_state(&state), Err("Memory page 0FFFFF is out of range".into()));
+        assert_eq!(machine.registers.get_a(), 0);
+
+        let mut state = saved(&Machine::new());
+        state.f = "123".to_string();
+        assert!(machine.restore_state(&state).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// read or write
+    Access,
+}
+
+impl WatchKind {
+    /// r, w or rw
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "r" => Some(WatchKind::Read),
+            "w" => Some(WatchKind::Write),
+            "rw" => Some(WatchKind::Access),
+            _ => None,
+        }
+    }
+    fn matches(&self, kind: WatchKind) -> bool { *self == WatchKind::Access || *self == kind }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "r"),
+            WatchKind::Write => write!(f, "w"),
+            WatchKind::Access => write!(f, "rw"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Watchpoint {
+    pub address: usize,
+    pub len: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn overlaps(&self, address: usize, len: usize) -> bool {
+        address < self.address.saturating_add(self.len)
+            && self.address < address.saturating_add(len)
+    }
+}
+
+/// Access that matched a watchpoint. Reads have old == new.
+#[derive(Debug, Clone)]
+pub struct WatchHit {
+    pub address: usize,
+    /// Read or Write
+    pub kind: WatchKind,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02X}")).collect::<String>();
+        match self.kind {
+            WatchKind::Write => {
+                write!(f, "w {:06X}: {} -> {}", self.address, hex(&self.old), hex(&self.new))
+            }
+            _ => write!(f, "r {:06X}: {}", self.address, hex(&self.new)),
+        }
+    }
+}
+
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    /// first matching access since the last take_hit
+    hit: Option<WatchHit>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self { Self { watchpoints: Vec::new(), hit: None } }
+
+    pub fn add(&mut self, watchpoint: Watchpoint) { self.watchpoints.push(watchpoint); }
+    /// removes all watchpoints starting at address
+    pub fn remove(&mut self, address: usize) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.address != address);
+        self.watchpoints.len() != len
+    }
+    pub fn clear(&mut self) { self.watchpoints.clear(); }
+    pub fn get_all(&self) -> &[Watchpoint] { &self.watchpoints }
+
+    pub fn take_hit(&mut self) -> Option<WatchHit> { self.hit.take() }
+
+    /// access of kind (Read or Write) touches a watched range
+    pub fn matches(&self, address: usize, len: usize, kind: WatchKind) -> bool {
+        self.watchpoints.iter().any(|watchpoint| {
+            watchpoint.kind.matches(kind) && watchpoint.overlaps(address, len)
+        })
+    }
+
+    /// record the access if it touches a watched range
+    pub fn note(&mut self, address: usize, kind: WatchKind, old: &[u8], new: &[u8]) {
+        if self.hit.is_some() {
+            return;
+        }
+        if self.matches(address, new.len(), kind) {
+            self.hit = Some(WatchHit { address, kind, old: old.to_vec(), new: new.to_vec() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watch(address: usize, len: usize, kind: WatchKind) -> Watchpoint {
+        Watchpoint { address, len, kind }
+    }
+
+    #[test]
+    fn matches_overlapping_accesses_of_its_kind() {
+        let watchpoint = watch(0x100, 3, WatchKind::Write);
+        assert!(watchpoint.overlaps(0xFE, 3));
+        assert!(watchpoint.overlaps(0x102, 1));
+        assert!(!watchpoint.overlaps(0x103, 3));
+        assert!(!watchpoint.overlaps(0xFD, 3));
+        assert!(!watchpoint.kind.matches(WatchKind::Read));
+        assert!(WatchKind::Access.matches(WatchKind::Read));
+    }
+
+    #[test]
+    fn huge_ranges_do_not_overflow() {
+        let watchpoint = watch(usize::MAX - 1, usize::MAX, WatchKind::Access);
+        assert!(watchpoint.overlaps(usize::MAX - 1, usize::MAX));
+        assert!(!watchpoint.overlaps(0, 3));
+    }
+
+    #[test]
+    fn keeps_the_first_hit() {
+        let mut watchpoints = Watchpoints::new();
+        watchpoints.add(watch(0x100, 3, WatchKind::Write));
+        watchpoints.note(0x100, WatchKind::Read, &[1], &[1]);
+        assert!(watchpoints.take_hit().is_none());
+        watchpoints.note(0x101, WatchKind::Write, &[1], &[2]);
+        watchpoints.note(0x102, WatchKind::Write, &[3], &[4]);
+        assert_eq!(watchpoints.take_hit().unwrap().to_string(), "w 000101: 01 -> 02");
+        assert!(watchpoints.take_hit().is_none());
+    }
+}
@@ -1,31 +1,45 @@
+mod batch;
+mod call_stack;
+mod disassembler;
+mod expression;
+mod history;
+mod loader;
 mod machine;
 mod processor;
+mod profile;
 mod sic_xe;
-
+mod snapshot;
+mod symbols;
+#[cfg(test)]
+mod temp_file;
+mod timing;
+mod trace;
+
+use expression::Expression;
+use machine::device_config::DeviceConfig;
+use machine::screen::Screen;
+use machine::watchpoints::{WatchKind, Watchpoint};
 use machine::Machine;
 use processor::Processor;
+use std::process::ExitCode;
+use timing::CycleCosts;
 use tokio::time::{self, Duration};
 
 use crate::processor::{ProcessorExt, ProcessorHandle};
+use crate::sic_xe::MASK_WORD;
 
 use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind};
 use futures::{StreamExt};
 use ratatui::{
     layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::Line,
     widgets::{Block, Borders, Paragraph},
     DefaultTerminal, Frame,
 };
 
-fn test_processor() {
-    let processor_ptr = Processor::new_handle();
-    // processor_ptr.load_file("./tests/arith.obj");
-    // processor_ptr.load_file("./tests/horner.obj");
-    processor_ptr.load_file("./tests/rec.obj");
-    processor_ptr.start();
-    loop {}
-}
+/// rows of each table in the Profile pane
+const PROFILE_PANE_ROWS: usize = 6;
 
 fn test_machine() {
     // write HELLO: to output
@@ -68,14 +82,44 @@ fn test_machine() {
 }
 
 #[tokio::main]
-async fn main() -> color_eyre::Result<()> {
+async fn main() -> color_eyre::Result<ExitCode> {
     // test_machine();
-    // test_processor();
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "run") {
+        return Ok(batch::main(&args[2..]));
+    }
+    if args.get(1).is_some_and(|arg| arg == "disasm") {
+        return Ok(disassembler::main(&args[2..]));
+    }
+
+    // sic_xe_simulator [--devices <file>]
+    let device_config = match args.get(1..) {
+        Some([option, file]) if option == "--devices" => match DeviceConfig::read(file) {
+            Ok(config) => Some(config),
+            Err(error) => {
+                eprintln!("{error}");
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+        Some([]) | None => None,
+        Some(_) => {
+            eprintln!("usage: sic_xe_simulator [--devices <file>] | run ... | disasm <prog.obj>");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    let app = match App::new(device_config) {
+        Ok(app) => app,
+        Err(error) => {
+            eprintln!("{error}");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
     color_eyre::install()?;
     let terminal = ratatui::init();
-    let result = App::new().run(terminal).await;
+    let result = app.run(terminal).await;
     ratatui::restore();
-    result
+    result.map(|()| ExitCode::SUCCESS)
 }
 
 // Ratatui
@@ -85,19 +129,45 @@ pub struct App {
 
     command_buffer: String,
     showing_memory_location: usize,
+    /// output of the last command, shown in the Info pane
+    message: Vec<String>,
+    /// Some -> keys go to the queue of this input device instead of the command line
+    input_device: Option<usize>,
+    /// applied again on reset
+    device_config: Option<DeviceConfig>,
+    /// from the last `cycles <file>`, applied again on reset
+    cycle_costs: Option<CycleCosts>,
 
     processor_ptr: ProcessorHandle,
 }
 
 impl App {
     /// Construct a new instance of [`App`].
-    pub fn new() -> Self {
-        Self {
+    pub fn new(device_config: Option<DeviceConfig>) -> Result<Self, String> {
+        Ok(Self {
             running: false,
-            processor_ptr: Processor::new_handle(),
+            processor_ptr: App::new_processor(&device_config, &None)?,
             command_buffer: String::new(),
             showing_memory_location: 0,
+            message: Vec::new(),
+            input_device: None,
+            device_config,
+            cycle_costs: None,
+        })
+    }
+
+    fn new_processor(
+        device_config: &Option<DeviceConfig>,
+        cycle_costs: &Option<CycleCosts>,
+    ) -> Result<ProcessorHandle, String> {
+        let processor_ptr = Processor::new_handle();
+        if let Some(config) = device_config {
+            config.apply(&mut processor_ptr.lock().unwrap().machine)?;
+        }
+        if let Some(costs) = cycle_costs {
+            processor_ptr.lock().unwrap().set_costs(costs.clone());
         }
+        Ok(processor_ptr)
     }
 
     /// Run the application's main loop.
@@ -162,24 +232,46 @@ impl App {
             ])
             .split(main_chunks[0]);
 
-        // UPPER ROW: [ memory ][ disasm ]
-        let upper_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([
+        // UPPER ROW: [ memory ][ disasm ][ profile ]
+        let profile_lines = processor.profile_report(Some(PROFILE_PANE_ROWS));
+        let upper_constraints = if profile_lines.is_some() {
+            vec![
+                Constraint::Percentage(34), // memory
+                Constraint::Percentage(36), // disasm
+                Constraint::Percentage(30), // profile
+            ]
+        } else {
+            vec![
                 Constraint::Percentage(50), // memory
                 Constraint::Percentage(50), // disasm
-            ])
+            ]
+        };
+        let upper_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(upper_constraints)
             .split(top_chunks[0]);
 
-        // ===== LOWER ROW: [ registers ][ output ][ info ] =====
-        let lower_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([
+        // ===== LOWER ROW: [ registers ][ output ][ screen ][ info ] =====
+        let screen_lines = processor.machine.screen_lines();
+        let lower_constraints = if screen_lines.is_some() {
+            vec![
+                Constraint::Percentage(25), // registers
+                Constraint::Percentage(20), // output
+                Constraint::Percentage(30), // screen
+                Constraint::Percentage(25), // info
+            ]
+        } else {
+            vec![
                 Constraint::Percentage(30), // registers
                 Constraint::Percentage(40), // output
                 Constraint::Percentage(30), // info
-            ])
+            ]
+        };
+        let lower_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(lower_constraints)
             .split(top_chunks[1]);
+        let info_chunk = lower_chunks[lower_chunks.len() - 1];
 
         // ===== MEMORY PANE =====
         let mut mem_lines = Vec::new();
@@ -195,8 +287,12 @@ impl App {
                 line_value = String::new();
                 line_value.push_str(&format!("{:04x}: ", memory_location));
             }
-            line_value
-                .push_str(&format!("{:0>2x} ", processor.machine.memory.get_byte(memory_location)));
+            if processor.machine.memory.is_valid(memory_location, 1) {
+                let byte = processor.machine.memory.get_byte(memory_location);
+                line_value.push_str(&format!("{:0>2x} ", byte));
+            } else {
+                line_value.push_str("-- ");
+            }
         }
         if !line_value.is_empty() {
             mem_lines.push(Line::from(line_value));
@@ -210,13 +306,29 @@ impl App {
         let mem_widget = Paragraph::new(mem_lines).block(mem_block);
         frame.render_widget(mem_widget, upper_chunks[0]);
 
+        // disassembly over backtrace
+        let disasm_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(22), // disassembly
+                Constraint::Min(3),     // backtrace
+            ])
+            .split(upper_chunks[1]);
+
         // ===== DISASSEMBLY PANE =====
         let mut disasm_lines: Vec<Line> = Vec::new();
         let mut addr = processor.machine.registers.get_pc() as usize;
 
         for _ in 0..20 {
             let (len, text) = processor.disassemble_at(addr);
-            disasm_lines.push(Line::from(text));
+            let mut style = Style::default();
+            if processor.is_breakpoint(addr) {
+                style = style.fg(Color::Red);
+            }
+            if processor.get_hit_breakpoint() == Some(addr) {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            disasm_lines.push(Line::from(text).style(style));
             addr = addr.saturating_add(len);
         }
 
@@ -226,20 +338,104 @@ impl App {
             .title("Disassembly")
             .title_style(Style::default().fg(Color::Green));
         let disasm_widget = Paragraph::new(disasm_lines).block(disasm_block);
-        frame.render_widget(disasm_widget, upper_chunks[1]);
+        frame.render_widget(disasm_widget, disasm_chunks[0]);
+
+        // ===== BACKTRACE PANE =====
+        let call_stack = processor.get_call_stack();
+        let mut backtrace_lines = Vec::new();
+        if let Some(mismatch) = call_stack.get_mismatch() {
+            backtrace_lines
+                .push(Line::from(mismatch.to_string()).style(Style::default().fg(Color::Red)));
+        }
+        let pc = (processor.machine.registers.get_pc() & MASK_WORD) as usize;
+        backtrace_lines.push(Line::from(format!("   at {}", processor.symbol_near(pc))));
+        for (depth, call) in call_stack.get_frames().iter().rev().enumerate() {
+            let target = processor.symbol_near(call.target);
+            let call_site = processor.symbol_near(call.call_site);
+            backtrace_lines.push(Line::from(format!("#{depth:<2} {target:12} <- {call_site}")));
+        }
+        let backtrace_block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Green))
+            .title("Backtrace")
+            .title_style(Style::default().fg(Color::Green));
+        let backtrace_widget = Paragraph::new(backtrace_lines).block(backtrace_block);
+        frame.render_widget(backtrace_widget, disasm_chunks[1]);
+
+        // ===== PROFILE PANE =====
+        if let Some(lines) = profile_lines {
+            let profile_block = Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title("Profile")
+                .title_style(Style::default().fg(Color::Yellow));
+            let profile_lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
+            let profile_widget = Paragraph::new(profile_lines).block(profile_block);
+            frame.render_widget(profile_widget, upper_chunks[2]);
+        }
 
         // ===== PROCESSOR PANE =====
-        let regs_lines = vec![
+        let mut regs_lines = vec![
             Line::from(format!(" A = {:6x}", processor.machine.registers.get_a())),
             Line::from(format!(" X = {:6x}", processor.machine.registers.get_x())),
             Line::from(format!(" L = {:6x}", processor.machine.registers.get_l())),
             Line::from(format!(" B = {:6x}", processor.machine.registers.get_b())),
             Line::from(format!(" S = {:6x}", processor.machine.registers.get_s())),
             Line::from(format!(" T = {:6x}", processor.machine.registers.get_t())),
+            Line::from(format!(" F = {}", processor.machine.registers.get_f())),
             Line::from(format!("PC = {:6x}", processor.machine.registers.get_pc())),
-            Line::from(format!("SW = {:6x}", processor.machine.registers.get_sw())),
+            Line::from(format!("SW = {:6x}", processor.machine.registers.get_sw() & MASK_WORD)),
+            Line::from(format!(
+                "CC = {:?}, {}, MASK = {:04b}, ICODE = {:02x}",
+                processor.machine.registers.get_cc(),
+                if processor.machine.registers.is_supervisor() { "supervisor" } else { "user" },
+                processor.machine.registers.get_mask(),
+                processor.machine.registers.get_icode(),
+            )),
             Line::from(format!("Speed in hz: {}", processor.get_speed())),
+            Line::from(format!("Cycles: {}", processor.get_cycles())),
+            Line::from(format!("History: {} steps", processor.get_history_len())),
         ];
+        if let Some(address) = processor.get_hit_breakpoint() {
+            regs_lines.push(
+                Line::from(format!("BREAKPOINT at {address:06X}"))
+                    .style(Style::default().fg(Color::Red)),
+            );
+        }
+        if let Some(error) = processor.get_condition_error() {
+            regs_lines.push(
+                Line::from(format!("CONDITION {error}")).style(Style::default().fg(Color::Red)),
+            );
+        }
+        if let Some((pc, hit)) = processor.get_hit_watchpoint() {
+            regs_lines.push(
+                Line::from(format!("WATCH {hit} at {pc:06X}"))
+                    .style(Style::default().fg(Color::Red)),
+            );
+        }
+        if let Some(fault) = processor.get_fault() {
+            regs_lines.push(
+                Line::from(format!("FAULT: {fault}")).style(Style::default().fg(Color::Red)),
+            );
+        }
+        if processor.is_waiting() {
+            regs_lines.push(
+                Line::from("WAITING for input (command: input)")
+                    .style(Style::default().fg(Color::Yellow)),
+            );
+        }
+        if let Some(file_name) = processor.get_trace_file() {
+            regs_lines.push(
+                Line::from(format!("TRACING to {file_name}"))
+                    .style(Style::default().fg(Color::Yellow)),
+            );
+        }
+        if let Some(pc) = processor.get_halted() {
+            regs_lines.push(
+                Line::from(format!("HALTED at {pc:06X} after {} steps", processor.get_steps()))
+                    .style(Style::default().fg(Color::Green)),
+            );
+        }
 
         let regs_block = Block::default()
             .borders(Borders::ALL)
@@ -251,11 +447,10 @@ impl App {
         frame.render_widget(regs_widget, lower_chunks[0]);
 
         // ===== OUTPUT PANE =====
-        let output_text = processor.machine.output_text();
-        let output_lines: Vec<Line> = if output_text.is_empty() {
-            vec![Line::from("No output yet")]
-        } else {
-            output_text.lines().map(Line::from).collect()
+        let output_lines: Vec<Line> = match processor.machine.output_text() {
+            None => vec![Line::from("Device 1 isn't kept in memory")],
+            Some("") => vec![Line::from("No output yet")],
+            Some(output_text) => output_text.lines().map(Line::from).collect(),
         };
 
         let output_block = Block::default()
@@ -267,19 +462,63 @@ impl App {
         let output_widget = Paragraph::new(output_lines).block(output_block);
         frame.render_widget(output_widget, lower_chunks[1]);
 
+        // ===== SCREEN PANE =====
+        if let (Some(lines), Some(screen)) = (screen_lines, processor.machine.screen) {
+            let screen_block = Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan))
+                .title(format!("Screen {:04x} {}x{}", screen.address, screen.cols, screen.rows))
+                .title_style(Style::default().fg(Color::Cyan));
+            let screen_lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
+            let screen_widget = Paragraph::new(screen_lines).block(screen_block);
+            frame.render_widget(screen_widget, lower_chunks[2]);
+        }
+
         // ===== INFO PANE =====
-        let info_lines = vec![
+        // output of the last command first, the pane is too short for all of the help
+        let mut info_lines: Vec<Line> =
+            self.message.iter().map(|line| Line::from(line.as_str())).collect();
+        if !info_lines.is_empty() {
+            info_lines.push(Line::from(""));
+        }
+        info_lines.extend([
             Line::from("Commands:"),
             Line::from("  q            quit"),
             Line::from("  start        start processor"),
             Line::from("  stop         stop processor"),
             Line::from("  step         one step"),
             Line::from("  reset        resets simulator"),
-            Line::from("  load <file>  load program"),
-            Line::from("  f <hz>       set speed"),
+            Line::from("  load <file>... [at <loc>]"),
+            Line::from("               load and link programs"),
+            Line::from("  symbols <file> [at <loc>]"),
+            Line::from("               labels from .lst/symbol file"),
+            Line::from("  save <file>  save machine snapshot"),
+            Line::from("  restore <file> restore snapshot"),
+            Line::from("  disasm <loc> <loc> <file>"),
+            Line::from("               write range as source"),
+            Line::from("  trace on <file>|off"),
+            Line::from("               trace (.jsonl: JSON lines)"),
+            Line::from("  profile on|off|save <file>"),
+            Line::from("               count instructions"),
+            Line::from("  f <hz>       set speed, cycles/s"),
+            Line::from("  cycles <file> load cycle costs"),
             Line::from("  mem <addr>   show memory from addr"),
-            Line::from(""),
-        ];
+            Line::from("  break <loc> [if <expr>]"),
+            Line::from("               set breakpoint"),
+            Line::from("  delete [loc] delete breakpoint(s)"),
+            Line::from("  breaks       list breakpoints"),
+            Line::from("  continue     continue from breakpoint"),
+            Line::from("  watch <loc> [len] [r|w|rw]"),
+            Line::from("               stop on memory access"),
+            Line::from("  unwatch [loc] delete watchpoint(s)"),
+            Line::from("  back [n]     step back n steps"),
+            Line::from("  rcontinue    run back to breakpoint"),
+            Line::from("  input [dev]  type into device (0)"),
+            Line::from("  screen <loc> [cols rows]|off"),
+            Line::from("               video memory"),
+            Line::from("  halt [at <loc>|opcode <hex>|off]"),
+            Line::from("               set halt condition"),
+        ]);
 
         let info_block = Block::default()
             .borders(Borders::ALL)
@@ -288,16 +527,25 @@ impl App {
             .title_style(Style::default().fg(Color::Magenta));
 
         let info_widget = Paragraph::new(info_lines).block(info_block);
-        frame.render_widget(info_widget, lower_chunks[2]);
+        frame.render_widget(info_widget, info_chunk);
 
         // ===== CLI PANE =====
-        let prompt_line = Line::from(self.command_buffer.clone());
+        let (prompt_line, cli_title, cli_color) = match self.input_device {
+            Some(index) => {
+                let queued = processor.machine.input_queue(index).map_or(0, |queue| {
+                    queue.lock().unwrap().len()
+                });
+                let title = format!("Keyboard -> device {index:X} (Esc leaves)");
+                (Line::from(format!("{queued} bytes queued")), title, Color::LightGreen)
+            }
+            None => (Line::from(self.command_buffer.clone()), "Input".to_string(), Color::White),
+        };
 
         let cli_block = Block::default()
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::White))
-            .title("Input")
-            .title_style(Style::default().fg(Color::White));
+            .border_style(Style::default().fg(cli_color))
+            .title(cli_title)
+            .title_style(Style::default().fg(cli_color));
 
         let cli_widget = Paragraph::new(prompt_line).block(cli_block);
         frame.render_widget(cli_widget, main_chunks[1]);
@@ -318,6 +566,10 @@ impl App {
 
     /// Handles the key events and updates the state of [`App`].
     fn on_key_event(&mut self, key: KeyEvent) {
+        if let Some(index) = self.input_device {
+            self.on_input_key(index, key);
+            return;
+        }
         match (key.modifiers, key.code) {
             (_, KeyCode::Enter) => {
                 let cmd = self.command_buffer.trim().to_string();
@@ -336,6 +588,27 @@ impl App {
             _ => {}
         }
     }
+    /// Input mode: send the key to the device queue as ASCII.
+    fn on_input_key(&mut self, index: usize, key: KeyEvent) {
+        let byte = match key.code {
+            KeyCode::Esc => {
+                self.input_device = None;
+                return;
+            }
+            KeyCode::Enter => b'\n',
+            KeyCode::Backspace => 0x08,
+            KeyCode::Tab => b'\t',
+            KeyCode::Char(c) if c.is_ascii() => c as u8,
+            _ => return,
+        };
+        let queue = self.processor_ptr.lock().unwrap().machine.input_queue(index);
+        match queue {
+            Some(queue) => queue.lock().unwrap().push_back(byte),
+            // device was replaced, e.g. by reset
+            None => self.input_device = None,
+        }
+    }
+
     fn execute_command(&mut self, cmd: Vec<&str>) {
         match cmd.as_slice() {
             ["q"] => self.quit(),
@@ -348,10 +621,88 @@ impl App {
             ["stop"] => {
                 self.processor_ptr.stop();
             }
-            ["reset"] => {
-                self.processor_ptr = Processor::new_handle();
+            ["reset"] => match App::new_processor(&self.device_config, &self.cycle_costs) {
+                Ok(processor_ptr) => self.processor_ptr = processor_ptr,
+                Err(error) => self.message = vec![error],
+            },
+            ["load", files @ .., "at", location] if !files.is_empty() => {
+                match self.parse_location(location) {
+                    Some(address) => self.load(files, Some(address)),
+                    None => self.message = vec![format!("Invalid address: {location}")],
+                }
+            }
+            ["load", files @ ..] if !files.is_empty() => self.load(files, None),
+            ["symbols", file, "at", location] => match self.parse_location(location) {
+                Some(address) => self.load_symbols(file, Some(address)),
+                None => self.message = vec![format!("Invalid address: {location}")],
+            },
+            ["symbols", file] => self.load_symbols(file, None),
+            ["save", file] => {
+                self.message = match self.processor_ptr.save_snapshot(file) {
+                    Ok(()) => vec![format!("Saved to {file}")],
+                    Err(error) => vec![error],
+                };
+            }
+            ["restore", file] => self.restore(file),
+            ["disasm", start, end, file] => {
+                self.message = match (self.parse_location(start), self.parse_location(end)) {
+                    (Some(start), Some(end)) => {
+                        match self.processor_ptr.save_source(file, start, end) {
+                            Ok(()) => vec![format!("Wrote {start:06X}..{end:06X} to {file}")],
+                            Err(error) => vec![error],
+                        }
+                    }
+                    _ => vec![format!("Invalid range: {start} {end}")],
+                };
+            }
+            ["trace", "on", file] => {
+                let started = self.processor_ptr.lock().unwrap().start_trace(file);
+                self.message = match started {
+                    Ok(()) => vec![format!("Tracing to {file}")],
+                    Err(error) => vec![error],
+                };
+            }
+            ["trace", "off"] => {
+                let stopped = self.processor_ptr.lock().unwrap().stop_trace();
+                self.message = match stopped {
+                    Some(Ok(file)) => vec![format!("Trace written to {file}")],
+                    Some(Err(error)) => vec![error],
+                    None => vec!["Not tracing".to_string()],
+                };
+            }
+            ["profile", "on"] => {
+                self.processor_ptr.lock().unwrap().start_profile();
+                self.message = vec!["Profiling".to_string()];
+            }
+            ["profile", "off"] => {
+                let profile = self.processor_ptr.lock().unwrap().stop_profile();
+                self.message = match profile {
+                    Some(profile) => {
+                        vec![format!("Profiled {} instructions", profile.get_instructions())]
+                    }
+                    None => vec!["Not profiling".to_string()],
+                };
+            }
+            ["profile", "save", file] => {
+                let report = self.processor_ptr.lock().unwrap().profile_report(None);
+                self.message = match report {
+                    Some(report) => match std::fs::write(file, report.join("\n") + "\n") {
+                        Ok(()) => vec![format!("Profile written to {file}")],
+                        Err(error) => vec![format!("Could not write {file}: {error}")],
+                    },
+                    None => vec!["Not profiling".to_string()],
+                };
+            }
+            ["cycles", file] => {
+                self.message = match CycleCosts::read(file) {
+                    Ok(costs) => {
+                        self.processor_ptr.lock().unwrap().set_costs(costs.clone());
+                        self.cycle_costs = Some(costs);
+                        vec![format!("Cycle costs from {file}")]
+                    }
+                    Err(error) => vec![error],
+                };
             }
-            ["load", file] => self.processor_ptr.load_file(file),
             ["f", hz] => {
                 if let Ok(value) = hz.parse::<i64>() {
                     self.processor_ptr.set_speed(value);
@@ -362,10 +713,245 @@ impl App {
                     self.showing_memory_location = value;
                 }
             }
+            ["break", location] => match self.parse_location(location) {
+                Some(address) => {
+                    self.processor_ptr.lock().unwrap().add_breakpoint(address, None);
+                    self.message = vec![format!("Breakpoint at {address:06X}")];
+                }
+                None => self.message = vec![format!("Unknown location: {location}")],
+            },
+            ["break", location, "if", condition @ ..] => {
+                let condition = condition.join(" ");
+                self.message = match (self.parse_location(location), Expression::parse(&condition))
+                {
+                    (Some(address), Ok(condition)) => {
+                        let mut processor = self.processor_ptr.lock().unwrap();
+                        // report unknown labels and such right away
+                        let mut message =
+                            vec![format!("Breakpoint at {address:06X} if {condition}")];
+                        if let Err(error) = condition.evaluate(&processor) {
+                            message.push(format!("Warning: {error}"));
+                        }
+                        processor.add_breakpoint(address, Some(condition));
+                        message
+                    }
+                    (None, _) => vec![format!("Unknown location: {location}")],
+                    (_, Err(error)) => vec![format!("Invalid condition: {error}")],
+                };
+            }
+            ["delete"] => {
+                self.processor_ptr.lock().unwrap().clear_breakpoints();
+                self.message = vec!["Deleted all breakpoints".to_string()];
+            }
+            ["delete", location] => {
+                let removed = match self.parse_location(location) {
+                    Some(address) => self.processor_ptr.lock().unwrap().remove_breakpoint(address),
+                    None => false,
+                };
+                self.message = if removed {
+                    vec![format!("Deleted breakpoint {location}")]
+                } else {
+                    vec![format!("No breakpoint at {location}")]
+                };
+            }
+            ["breaks"] => {
+                let processor = self.processor_ptr.lock().unwrap();
+                self.message = vec!["Breakpoints:".to_string()];
+                self.message.extend(processor.get_breakpoints().iter().map(|(address, condition)| {
+                    let mut line = format!("  {address:06X}");
+                    if let Some(label) = processor.symbol_at(*address) {
+                        line.push_str(&format!(" {label}"));
+                    }
+                    if let Some(condition) = condition {
+                        line.push_str(&format!(" if {condition}"));
+                    }
+                    line
+                }));
+            }
+            ["watch"] => {
+                let processor = self.processor_ptr.lock().unwrap();
+                let watchpoints = processor.machine.memory.watchpoints.get_all();
+                self.message = vec!["Watchpoints:".to_string()];
+                self.message.extend(watchpoints.iter().map(|w| {
+                    format!("  {:06X} len {} {}", w.address, w.len, w.kind)
+                }));
+            }
+            ["watch", location, args @ ..] => {
+                // [len] [r|w|rw], defaults to a written word
+                let mut len = 3;
+                let mut kind = WatchKind::Write;
+                let mut valid = args.len() <= 2;
+                for arg in args {
+                    if let Some(parsed) = WatchKind::parse(arg) {
+                        kind = parsed;
+                    } else if let Ok(parsed) = arg.parse::<usize>() {
+                        len = parsed.max(1);
+                    } else {
+                        valid = false;
+                    }
+                }
+                self.message = match self.parse_location(location) {
+                    Some(address) if valid => {
+                        let mut processor = self.processor_ptr.lock().unwrap();
+                        processor.machine.memory.watchpoints.add(Watchpoint { address, len, kind });
+                        vec![format!("Watchpoint {kind} at {address:06X} len {len}")]
+                    }
+                    Some(_) => vec!["Usage: watch <loc> [len] [r|w|rw]".to_string()],
+                    None => vec![format!("Unknown location: {location}")],
+                };
+            }
+            ["unwatch"] => {
+                self.processor_ptr.lock().unwrap().machine.memory.watchpoints.clear();
+                self.message = vec!["Deleted all watchpoints".to_string()];
+            }
+            ["unwatch", location] => {
+                let removed = match self.parse_location(location) {
+                    Some(address) => {
+                        let mut processor = self.processor_ptr.lock().unwrap();
+                        processor.machine.memory.watchpoints.remove(address)
+                    }
+                    None => false,
+                };
+                self.message = if removed {
+                    vec![format!("Deleted watchpoint {location}")]
+                } else {
+                    vec![format!("No watchpoint at {location}")]
+                };
+            }
+            ["continue"] => {
+                self.processor_ptr.start();
+            }
+            ["back"] => self.step_back(1),
+            ["back", n] => match n.parse::<usize>() {
+                Ok(n) => self.step_back(n),
+                Err(_) => self.message = vec!["Usage: back [n]".to_string()],
+            },
+            ["rcontinue"] => {
+                self.processor_ptr.stop();
+                let mut processor = self.processor_ptr.lock().unwrap();
+                let count = processor.reverse_continue();
+                self.message = vec![format!(
+                    "Stepped back {count} steps to {:06X}",
+                    processor.machine.registers.get_pc() & MASK_WORD
+                )];
+            }
+            ["input"] => self.enter_input_mode("0"),
+            ["input", device] => self.enter_input_mode(device),
+            ["screen", "off"] => {
+                self.processor_ptr.lock().unwrap().machine.screen = None;
+                self.message = vec!["Screen off".to_string()];
+            }
+            ["screen", location, size @ ..] => {
+                let size = match size {
+                    [] => Some((80, 25)),
+                    [cols, rows] => cols.parse::<usize>().ok().zip(rows.parse::<usize>().ok()),
+                    _ => None,
+                };
+                self.message = match (self.parse_location(location), size) {
+                    (Some(address), Some((cols, rows))) => {
+                        let screen = Screen { address, cols, rows };
+                        match self.processor_ptr.lock().unwrap().machine.set_screen(screen) {
+                            Ok(()) => vec![format!("Screen at {address:06X}, {cols}x{rows}")],
+                            Err(error) => vec![error],
+                        }
+                    }
+                    (None, _) => vec![format!("Unknown location: {location}")],
+                    _ => vec!["Usage: screen <loc> [cols rows]".to_string()],
+                };
+            }
+            ["halt"] => {
+                let processor = self.processor_ptr.lock().unwrap();
+                self.message = vec!["Halt on: J *".to_string()];
+                if let Some(address) = processor.get_halt_address() {
+                    self.message.push(format!("  address {address:06X}"));
+                }
+                if let Some(opcode) = processor.get_halt_opcode() {
+                    self.message.push(format!("  opcode {opcode:02X}"));
+                }
+            }
+            ["halt", "at", location] => {
+                self.message = match self.parse_location(location) {
+                    Some(address) => {
+                        self.processor_ptr.lock().unwrap().set_halt_address(Some(address));
+                        vec![format!("Halt at {address:06X}")]
+                    }
+                    None => vec![format!("Unknown location: {location}")],
+                };
+            }
+            ["halt", "opcode", opcode] => {
+                let hex = opcode.strip_prefix("0x").unwrap_or(opcode);
+                self.message = match u8::from_str_radix(hex, 16) {
+                    Ok(opcode) => {
+                        let mut processor = self.processor_ptr.lock().unwrap();
+                        processor.set_halt_opcode(Some(opcode));
+                        vec![format!("Halt on opcode {:02X}", opcode & 0xFC)]
+                    }
+                    Err(_) => vec![format!("Invalid opcode: {opcode}")],
+                };
+            }
+            ["halt", "off"] => {
+                let mut processor = self.processor_ptr.lock().unwrap();
+                processor.set_halt_address(None);
+                processor.set_halt_opcode(None);
+                self.message = vec!["Halt only on J *".to_string()];
+            }
             _ => {}
         }
     }
 
+    /// device in hex, like RD
+    fn enter_input_mode(&mut self, device: &str) {
+        let index = usize::from_str_radix(device, 16).ok();
+        let processor = self.processor_ptr.lock().unwrap();
+        self.message = match index.filter(|index| processor.machine.input_queue(*index).is_some()) {
+            Some(index) => {
+                self.input_device = Some(index);
+                vec![format!("Keys go to device {index:X}, Esc leaves")]
+            }
+            None => vec![format!("Device {device} isn't a keyboard")],
+        };
+    }
+
+    fn load(&mut self, files: &[&str], address: Option<usize>) {
+        self.message = match self.processor_ptr.load_files(files, address) {
+            Ok(()) => {
+                let pc = self.processor_ptr.lock().unwrap().machine.registers.get_pc();
+                vec![format!("Loaded {}, entry {pc:06X}", files.join(" "))]
+            }
+            Err(error) => error.lines().map(String::from).collect(),
+        };
+    }
+
+    fn load_symbols(&mut self, file: &str, address: Option<usize>) {
+        self.message = match self.processor_ptr.load_symbols(file, address) {
+            Ok(count) => vec![format!("{count} symbols from {file}")],
+            Err(error) => vec![error],
+        };
+    }
+
+    fn restore(&mut self, file: &str) {
+        self.processor_ptr.stop();
+        self.message = match self.processor_ptr.restore_snapshot(file) {
+            Ok(problems) => {
+                let pc = self.processor_ptr.lock().unwrap().machine.registers.get_pc();
+                let mut message = vec![format!("Restored {file}, PC {pc:06X}")];
+                message.extend(problems);
+                message
+            }
+            Err(error) => error.lines().map(String::from).collect(),
+        };
+    }
+
+    fn step_back(&mut self, n: usize) {
+        self.processor_ptr.stop();
+        let count = self.processor_ptr.lock().unwrap().step_back(n);
+        self.message = vec![format!("Stepped back {count} steps")];
+    }
+
+    fn parse_location(&self, location: &str) -> Option<usize> {
+        self.processor_ptr.lock().unwrap().parse_location(location)
+    }
+
     /// Set running to false to quit the application.
     fn quit(&mut self) { self.running = false; }
 }
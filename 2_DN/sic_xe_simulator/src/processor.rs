@@ -1,73 +1,587 @@
-extern crate chrono;
-extern crate timer;
-
 use std::{
-    fs::OpenOptions,
-    io::{self, BufRead},
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::Path,
     sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    machine::{opcodes::Opcode, Machine},
+    disassembler,
+    expression::Expression,
+    history::{History, UndoRecord},
+    loader::{self, ObjectProgram},
+    machine::{
+        float::SicFloat,
+        interrupts::{InterruptClass, ProgramCheck, STATUS_LEN},
+        opcodes::Opcode,
+        decode_cache::Decoded,
+        registers::Registers,
+        watchpoints::{WatchHit, WatchKind},
+        Machine,
+    },
     sic_xe::{
-        get_format_sic_f3_f4_bits, get_r1_r2, i24_to_u8arr, is_base_relative, is_format_f3,
-        is_format_f4, is_format_sic, is_immediate, is_pc_relative, resolve_address, u8arr_to_i24,
-        FormatSicF3F4Bits,
+        get_r1_r2, i24_to_u8arr, is_base_relative, is_immediate, is_pc_relative, resolve_address,
+        u8arr_to_i24, FormatSicF3F4Bits, MASK_WORD,
     },
+    call_stack::CallStack,
+    profile::Profile,
+    snapshot::Snapshot,
+    symbols,
+    timing::CycleCosts,
+    trace::{TraceRecord, Tracer},
 };
 
 const MAX_HZ: i64 = 1_000_000_000;
+/// most instructions the run loop executes in one slice, while holding the lock
+const MAX_SLICE: u64 = 10_000;
+/// longest sleep between slices, so a stop or a new speed is seen soon
+const MAX_SLEEP: Duration = Duration::from_millis(10);
 
 pub struct Processor {
     pub machine: Machine,
 
-    /// speed in Hz
+    /// speed in Hz, cycles per second
     speed: i64,
+    /// cycles each instruction takes
+    costs: CycleCosts,
+
+    /// the run loop thread executes instructions while set
+    running: bool,
+    /// counts starts, the run loop thread of an earlier one exits
+    run_id: u64,
+
+    /// set when the run stopped on a fault, cleared by reset
+    fault: Option<SimFault>,
+
+    /// the run loop stops before executing an instruction at these addresses,
+    /// if there is no condition or it is true
+    breakpoints: BTreeMap<usize, Option<Expression>>,
+    /// set when the run stopped at a breakpoint, cleared on start
+    hit_breakpoint: Option<usize>,
+    /// set when a breakpoint condition couldn't be evaluated, the run stops anyway
+    condition_error: Option<String>,
+    /// (pc, access) when the run stopped at a watchpoint, cleared on start
+    hit_watchpoint: Option<(i32, WatchHit)>,
+    /// label -> address
+    symbols: HashMap<String, usize>,
 
-    timer: timer::Timer,
-    guard: Option<timer::Guard>,
+    /// undo journal of executed instructions
+    history: History,
+    /// record history for stepping back, off in headless runs
+    journaling: bool,
+    /// instructions executed since start
+    steps: u64,
+    /// cycles they took
+    cycles: u64,
+    /// Some(pc) -> program ended there, nothing runs until loaded again
+    halted: Option<i32>,
+    /// halt before executing the instruction at this address
+    halt_address: Option<usize>,
+    /// halt before executing an instruction with this opcode
+    halt_opcode: Option<u8>,
+    /// the last RD found no input and is retried
+    waiting: bool,
+    /// Some -> executed instructions are written to the trace
+    tracer: Option<Tracer>,
+    /// Some -> executed instructions are counted
+    profile: Option<Profile>,
+    /// calls made by JSUB and not returned from yet
+    call_stack: CallStack,
+}
+
+/// Program check in supervisor mode. There is no kernel to take the program interrupt, so the
+/// run stops instead.
+#[derive(Debug, Clone)]
+pub struct SimFault {
+    /// address of the faulting instruction
+    pub pc: i32,
+    pub cause: ProgramCheck,
+}
+
+impl fmt::Display for SimFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {:06X}", self.cause, self.pc)
+    }
 }
 
 pub type ProcessorHandle = Arc<Mutex<Processor>>;
 
+/// Why Processor::run returned
+#[derive(Debug, Clone)]
+pub enum StopReason {
+    Halted,
+    Fault(SimFault),
+    /// max_steps instructions executed
+    StepLimit,
+}
+
 impl Processor {
     pub fn new_handle() -> ProcessorHandle { Arc::new(Mutex::new(Processor::new())) }
     fn new() -> Self {
-        Self { machine: Machine::new(), speed: 1000, timer: timer::Timer::new(), guard: None }
+        Self {
+            machine: Machine::new(),
+            speed: 1000,
+            costs: CycleCosts::new(),
+            running: false,
+            run_id: 0,
+            fault: None,
+            breakpoints: BTreeMap::new(),
+            hit_breakpoint: None,
+            condition_error: None,
+            hit_watchpoint: None,
+            symbols: HashMap::new(),
+            history: History::new(),
+            journaling: true,
+            steps: 0,
+            cycles: 0,
+            halted: None,
+            halt_address: None,
+            halt_opcode: None,
+            waiting: false,
+            tracer: None,
+            profile: None,
+            call_stack: CallStack::new(),
+        }
     }
 
     pub fn get_speed(&self) -> i64 { self.speed }
+    pub fn get_fault(&self) -> Option<&SimFault> { self.fault.as_ref() }
+    pub fn get_steps(&self) -> u64 { self.steps }
+    pub fn get_cycles(&self) -> u64 { self.cycles }
+    pub fn set_costs(&mut self, costs: CycleCosts) { self.costs = costs; }
+    /// RD is waiting for input
+    pub fn is_waiting(&self) -> bool { self.waiting }
+    pub fn set_journaling(&mut self, val: bool) {
+        self.journaling = val;
+        if !val {
+            self.history.clear();
+        }
+    }
 
-    fn execute_instruction(&mut self) -> () {
-        let byte = self.fetch();
-        let opcode = match Opcode::from_byte(byte & 0xFC) {
-            Some(opcode) => opcode,
-            None => {
-                Processor::invalid_opcode(byte);
-                return;
-            }
+
+    // halting
+    pub fn is_halted(&self) -> bool { self.halted.is_some() }
+    /// address of the instruction the program halted on
+    pub fn get_halted(&self) -> Option<i32> { self.halted }
+    pub fn get_halt_address(&self) -> Option<usize> { self.halt_address }
+    pub fn set_halt_address(&mut self, address: Option<usize>) {
+        self.halt_address = address;
+    }
+    pub fn get_halt_opcode(&self) -> Option<u8> { self.halt_opcode }
+    pub fn set_halt_opcode(&mut self, opcode: Option<u8>) {
+        self.halt_opcode = opcode.map(|opcode| opcode & 0xFC);
+    }
+
+    /// an interrupt may still come and change what the program does
+    fn can_be_interrupted(&self) -> bool {
+        self.machine.has_armed_timer() || self.machine.interrupts.has_pending()
+    }
+    /// idle for good, or at the configured halt address or opcode
+    fn halts_before(&self) -> bool {
+        let registers = &self.machine.registers;
+        if registers.is_idle() {
+            return !self.can_be_interrupted();
+        }
+        let pc = (registers.get_pc() & MASK_WORD) as usize;
+        let memory = &self.machine.memory;
+        let opcode = memory.is_valid(pc, 1).then(|| memory.get_byte(pc) & 0xFC);
+        self.halt_address == Some(pc) || (self.halt_opcode.is_some() && opcode == self.halt_opcode)
+    }
+    fn halt(&mut self, pc: i32) {
+        self.halted = Some(pc);
+        self.running = false;
+    }
+
+    // breakpoints
+    pub fn add_breakpoint(&mut self, address: usize, condition: Option<Expression>) {
+        self.breakpoints.insert(address, condition);
+    }
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+    pub fn clear_breakpoints(&mut self) { self.breakpoints.clear(); }
+    pub fn get_breakpoints(&self) -> &BTreeMap<usize, Option<Expression>> { &self.breakpoints }
+    pub fn is_breakpoint(&self, address: usize) -> bool { self.breakpoints.contains_key(&address) }
+    pub fn get_hit_breakpoint(&self) -> Option<usize> { self.hit_breakpoint }
+    pub fn get_condition_error(&self) -> Option<&str> { self.condition_error.as_deref() }
+    pub fn get_hit_watchpoint(&self) -> Option<&(i32, WatchHit)> { self.hit_watchpoint.as_ref() }
+    /// PC is at a breakpoint and its condition holds
+    fn at_breakpoint(&mut self) -> bool {
+        let pc = (self.machine.registers.get_pc() & MASK_WORD) as usize;
+        let condition = match self.breakpoints.get(&pc) {
+            None => return false,
+            Some(None) => return true,
+            Some(Some(condition)) => condition,
         };
-        // println!("\n{:?}", opcode);
-        // println!("\nbyte1={:08b}", byte);
+        match condition.is_true(self) {
+            Ok(hit) => hit,
+            Err(error) => {
+                self.condition_error = Some(format!("{condition}: {error}"));
+                true
+            }
+        }
+    }
+
+    // symbols
+    pub fn add_symbol(&mut self, name: &str, address: usize) {
+        self.symbols.insert(name.to_string(), address);
+    }
+    pub fn get_symbol(&self, name: &str) -> Option<usize> { self.symbols.get(name).copied() }
+    /// first label (alphabetically) at address
+    pub fn symbol_at(&self, address: usize) -> Option<&str> {
+        self.symbols
+            .iter()
+            .filter(|(_, symbol_address)| **symbol_address == address)
+            .map(|(name, _)| name.as_str())
+            .min()
+    }
 
-        if self.exec_f1(&opcode) {
-            self.print_state();
+    /// nearest label at or before address, with the offset from it: `fakrec+3`, or hex
+    pub fn symbol_near(&self, address: usize) -> String {
+        let nearest = self
+            .symbols
+            .iter()
+            .filter(|(_, symbol_address)| **symbol_address <= address)
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)));
+        match nearest {
+            Some((name, symbol_address)) if *symbol_address == address => name.clone(),
+            Some((name, symbol_address)) => format!("{name}+{:X}", address - symbol_address),
+            None => format!("{address:06X}"),
+        }
+    }
+
+    /// label, 0x prefixed hex or decimal address
+    pub fn parse_location(&self, text: &str) -> Option<usize> {
+        if let Some(address) = self.get_symbol(text) {
+            return Some(address);
+        }
+        match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(hex) => usize::from_str_radix(hex, 16).ok(),
+            None => text.parse::<usize>().ok(),
+        }
+    }
+
+    // tracing
+    /// Write a record of every executed instruction to the file (see trace), replacing
+    /// the current trace.
+    pub fn start_trace(&mut self, file_name: &str) -> Result<(), String> {
+        let tracer = Tracer::create(file_name)?;
+        if let Some(Err(error)) = self.stop_trace() {
+            return Err(error);
+        }
+        self.tracer = Some(tracer);
+        Ok(())
+    }
+    /// return:
+    /// \   Some(Ok(file name)) -> trace written
+    /// \   Some(Err) -> writing it failed
+    /// \   None -> wasn't tracing
+    pub fn stop_trace(&mut self) -> Option<Result<String, String>> {
+        let tracer = self.tracer.take()?;
+        let file_name = tracer.file_name().to_string();
+        Some(tracer.finish().map(|_| file_name))
+    }
+    pub fn get_trace_file(&self) -> Option<&str> {
+        self.tracer.as_ref().map(|tracer| tracer.file_name())
+    }
+
+    pub fn get_call_stack(&self) -> &CallStack { &self.call_stack }
+
+    // profiling
+    /// Count executed instructions from now on (see profile), the old counts are dropped.
+    pub fn start_profile(&mut self) { self.profile = Some(Profile::new()); }
+    pub fn stop_profile(&mut self) -> Option<Profile> { self.profile.take() }
+    /// see Profile::report, labels from the symbols
+    pub fn profile_report(&self, limit: Option<usize>) -> Option<Vec<String>> {
+        let symbol_at = |address: usize| self.symbol_at(address).map(str::to_string);
+        Some(self.profile.as_ref()?.report(&symbol_at, limit))
+    }
+
+    // snapshots
+    pub fn snapshot(&self) -> Snapshot {
+        let symbols = self
+            .symbols
+            .iter()
+            .map(|(name, address)| (name.clone(), format!("{address:06X}")))
+            .collect();
+        Snapshot::new(self.speed, self.steps, self.cycles, symbols, self.machine.save_state())
+    }
+    /// Continue from a snapshot, like after loading: history, fault and halt are cleared.
+    /// return: see Machine::restore_state
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<Vec<String>, String> {
+        let symbols = snapshot
+            .symbols
+            .iter()
+            .map(|(name, address)| match usize::from_str_radix(address, 16) {
+                Ok(address) => Ok((name.clone(), address)),
+                Err(_) => Err(format!("Invalid address {address} of {name}")),
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        let problems = self.machine.restore_state(&snapshot.machine)?;
+        self.symbols = symbols;
+        self.speed = snapshot.speed.clamp(1, MAX_HZ);
+        self.steps = snapshot.steps;
+        self.cycles = snapshot.cycles;
+        self.clear_history();
+        self.call_stack.clear();
+        self.fault = None;
+        self.halted = None;
+        self.waiting = false;
+        Ok(problems)
+    }
+
+    // reverse execution
+    pub fn get_history_len(&self) -> usize { self.history.len() }
+    pub fn clear_history(&mut self) { self.history.clear(); }
+
+    /// Undo the last executed instruction.
+    /// return:
+    /// \   Some -> the undone record
+    /// \   None -> history is empty
+    fn undo(&mut self) -> Option<UndoRecord> {
+        let record = self.history.pop()?;
+        // newest first, so a byte written twice gets its oldest value back
+        for (address, byte) in record.memory.iter().rev() {
+            self.machine.memory.set_byte(*address, *byte);
+        }
+        self.machine.registers = record.registers.clone();
+        self.machine.interrupts.set_interval_timer(record.interval_timer);
+        if let Some(call_stack) = &record.call_stack {
+            self.call_stack = call_stack.clone();
+        }
+        self.steps = self.steps.saturating_sub(1);
+        self.cycles = record.cycles;
+
+        self.fault = None;
+        self.halted = None;
+        self.hit_breakpoint = None;
+        self.hit_watchpoint = None;
+        self.condition_error = None;
+        Some(record)
+    }
+
+    /// Step back up to n instructions, return how many were undone.
+    pub fn step_back(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.undo().is_some()).count()
+    }
+
+    /// Step back until PC is at a breakpoint, an undone instruction wrote a watched range or
+    /// history runs out. Return how many instructions were undone.
+    pub fn reverse_continue(&mut self) -> usize {
+        let mut count = 0;
+        while let Some(record) = self.undo() {
+            count += 1;
+            let watched = record.memory.iter().any(|(address, _)| {
+                self.machine.memory.watchpoints.matches(*address, 1, WatchKind::Write)
+            });
+            if self.at_breakpoint() {
+                self.hit_breakpoint = Some((self.machine.registers.get_pc() & MASK_WORD) as usize);
+                break;
+            }
+            if watched {
+                break;
+            }
+        }
+        count
+    }
+
+    /// Run at full speed on the calling thread, ignoring breakpoints and watchpoints.
+    /// max_steps: None -> until halted or faulted
+    pub fn run(&mut self, max_steps: Option<u64>) -> StopReason {
+        let mut steps = 0;
+        loop {
+            if let Some(fault) = &self.fault {
+                return StopReason::Fault(fault.clone());
+            }
+            if self.is_halted() {
+                return StopReason::Halted;
+            }
+            if max_steps.is_some_and(|max_steps| steps >= max_steps) {
+                return StopReason::StepLimit;
+            }
+            self.execute_instruction();
+            steps += 1;
+        }
+    }
+
+    /// One step of the run loop: stops before an instruction at a breakpoint.
+    fn run_step(&mut self) {
+        if self.at_breakpoint() {
+            self.hit_breakpoint = Some((self.machine.registers.get_pc() & MASK_WORD) as usize);
+            self.running = false;
             return;
         }
+        self.execute_instruction();
+    }
 
-        let operand = self.fetch();
-        // println!("byte2={:08b}", operand);
-        if self.exec_f2(&opcode, &operand) {
-            self.print_state();
+    /// Execute one instruction and journal it, so it can be stepped back.
+    /// Halts instead on a jump to itself (`J *`), as nothing changes after it anymore.
+    fn execute_instruction(&mut self) -> () {
+        if self.fault.is_some() || self.halted.is_some() {
             return;
         }
+        let pc = self.machine.registers.get_pc();
+        if self.halts_before() {
+            self.halt(pc);
+            return;
+        }
+
+        let registers = self.machine.registers.clone();
+        let interval_timer = self.machine.interrupts.get_interval_timer();
+        let cycles = self.cycles;
+        // decoded before it runs, the effective address depends on the registers
+        let traced = (self.tracer.is_some() && !registers.is_idle()).then(|| self.trace_decode());
+        let executed = (!registers.is_idle()).then(|| self.call_decode());
+        let (opcode, call) = executed.unwrap_or((0, None));
+        let calls_or_returns = executed.is_some()
+            && (opcode & 0xFC == Opcode::Jsub as u8 || opcode & 0xFC == Opcode::Rsub as u8);
+        let call_stack = (self.journaling && calls_or_returns).then(|| self.call_stack.clone());
+        if self.journaling || traced.is_some() {
+            self.machine.memory.begin_journal();
+        }
+        self.cycle();
+        let memory = self.machine.memory.end_journal();
+
+        // a fault stops before anything changed
+        if self.fault.is_some() {
+            return;
+        }
+        self.steps += 1;
+        let executed = executed.is_some() && !self.waiting;
+        let after = &self.machine.registers;
+        // an immediate operand has no effective address, it's the jump target itself
+        let call = (opcode & 0xFC == Opcode::Jsub as u8)
+            .then(|| call.unwrap_or((after.get_pc() & MASK_WORD) as usize));
+        if executed && calls_or_returns {
+            let pc = (pc & MASK_WORD) as usize;
+            match call {
+                Some(target) => {
+                    self.call_stack.call(pc, target, (after.get_l() & MASK_WORD) as usize)
+                }
+                None => self.call_stack.ret(pc, (registers.get_l() & MASK_WORD) as usize),
+            }
+        }
+        if let Some(profile) = self.profile.as_mut().filter(|_| executed) {
+            profile.record((pc & MASK_WORD) as usize, opcode, call);
+        }
+        if let Some((bytes, instruction, ea)) = traced.filter(|_| !self.waiting) {
+            let pc = (pc & MASK_WORD) as usize;
+            let record =
+                TraceRecord::new(self.steps, pc, &bytes, instruction, ea, &self.machine, &memory);
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.write(&record);
+            }
+        }
+        if self.journaling {
+            let record = UndoRecord { registers, interval_timer, cycles, memory, call_stack };
+            self.history.push(record);
+        }
 
-        let third_byte = self.fetch();
-        // println!("byte3={:08b}", third_byte);
-        if !self.exec_sic_f3_f4(&opcode, &byte, &operand, &third_byte) {
-            panic!("??? exec failed. Looks like I forgot to add a opcode to arms!?");
+        let self_loop = !self.machine.registers.is_idle() && self.machine.registers.get_pc() == pc;
+        if self_loop && !self.waiting && !self.can_be_interrupted() {
+            self.halt(pc);
         }
+    }
+
+    /// (bytes, instruction, effective address) of the instruction at PC, for the trace
+    fn trace_decode(&self) -> (Vec<u8>, String, Option<usize>) {
+        let pc = (self.machine.registers.get_pc() & MASK_WORD) as usize;
+        let hex = |address: usize| Some(format!("{address:06X}"));
+        let instruction = disassembler::decode(&self.machine, pc, &hex);
+        let memory = &self.machine.memory;
+        let bytes = (pc..pc + instruction.len)
+            .take_while(|address| memory.is_valid(*address, 1))
+            .map(|address| memory.get_byte(address))
+            .collect();
+        let text = format!("{} {}", instruction.mnemonic, instruction.operand);
+        (bytes, text.trim_end().to_string(), instruction.effective)
+    }
+
+    /// (opcode, JSUB target) of the instruction at PC, for the call stack and the profile
+    fn call_decode(&self) -> (u8, Option<usize>) {
+        let pc = (self.machine.registers.get_pc() & MASK_WORD) as usize;
+        let memory = &self.machine.memory;
+        let opcode = if memory.is_valid(pc, 1) { memory.get_byte(pc) } else { 0 };
+        let call = (opcode & 0xFC == Opcode::Jsub as u8)
+            .then(|| disassembler::decode(&self.machine, pc, &|_| None).effective)
+            .flatten();
+        (opcode, call)
+    }
+
+    /// Execute one instruction (none while idle) and count its cycles, then count down the
+    /// timers and take any pending interrupt, so the saved PC points after the instruction.
+    /// A program check suppresses the instruction: registers are restored, stores never happened.
+    fn cycle(&mut self) {
+        let mut cycles = self.costs.get_idle();
+        if !self.machine.registers.is_idle() {
+            let pc = self.machine.registers.get_pc();
+            let registers = self.machine.registers.clone();
+            self.waiting = false;
+            cycles = self.decode_and_execute();
+            if self.waiting {
+                self.machine.registers.set_pc(pc);
+            }
+
+            // stop after the instruction, so the new value is visible
+            if let Some(hit) = self.machine.memory.watchpoints.take_hit() {
+                self.hit_watchpoint = Some((pc, hit));
+                self.running = false;
+            }
+
+            if self.machine.interrupts.is_pending(InterruptClass::Program) {
+                let next_pc = self.machine.registers.get_pc();
+                self.machine.registers = registers;
+                if self.machine.registers.is_supervisor() {
+                    let cause = self.machine.interrupts.take_program_check();
+                    self.fault = cause.map(|cause| SimFault { pc, cause });
+                    self.running = false;
+                    return;
+                }
+                self.machine.registers.set_pc(next_pc);
+            }
+        }
+        self.cycles += cycles;
+        self.machine.tick(self.steps + 1, cycles);
+        self.machine.service_interrupts();
+    }
+
+    /// Execute the instruction at PC, decoded once and then taken from the memory's decode cache.
+    /// return: cycles it took
+    fn decode_and_execute(&mut self) -> u64 {
+        let pc = (self.machine.registers.get_pc() & MASK_WORD) as usize;
+        let memory = &mut self.machine.memory;
+        let decoded = match memory.decode_cache.get(pc) {
+            Some(decoded) => decoded,
+            None => {
+                let get_byte =
+                    |address| memory.is_valid(address, 1).then(|| memory.get_byte(address));
+                match Decoded::read(get_byte, pc) {
+                    Ok(decoded) => {
+                        memory.decode_cache.insert(pc, decoded);
+                        decoded
+                    }
+                    Err((cause, len)) => {
+                        let registers = &mut self.machine.registers;
+                        registers.set_pc(registers.get_pc() + len as i32);
+                        Processor::program_check(&mut self.machine, cause);
+                        return self.costs.get_fetch_fault();
+                    }
+                }
+            }
+        };
+        let registers = &mut self.machine.registers;
+        registers.set_pc(registers.get_pc() + decoded.len() as i32);
+
+        match decoded {
+            Decoded::F1(opcode) => self.exec_f1(&opcode),
+            Decoded::F2(opcode, operand) => self.exec_f2(&opcode, &operand),
+            Decoded::SicF3F4(opcode, bits, addr) => self.exec_sic_f3_f4(&opcode, &bits, addr),
+        };
         self.print_state();
+        self.costs.cycles(&decoded)
     }
 
     fn print_state(&self) -> () {
@@ -88,14 +602,6 @@ impl Processor {
         // println!("-------------------------\n");
     }
 
-    /// fetch next 8b and pc++
-    fn fetch(&mut self) -> u8 {
-        let pc = self.machine.registers.get_pc();
-        // println!("pc=0x{:x}", pc);
-        self.machine.registers.set_pc(pc + 1);
-        self.machine.memory.get_byte(pc.try_into().unwrap())
-    }
-
     /// opcode: 8b
     /// return:
     /// \   true -> executed F1
@@ -103,13 +609,27 @@ impl Processor {
     fn exec_f1(&mut self, opcode: &Opcode) -> bool {
         match opcode {
             Opcode::Float => {
-                self.machine.registers.set_f(self.machine.registers.get_a().try_into().unwrap());
+                self.machine.registers.set_f(SicFloat::from_i24(self.machine.registers.get_a()));
+            }
+            Opcode::Fix => self.machine.registers.set_a(self.machine.registers.get_f().to_i24()),
+            Opcode::Norm => {
+                self.machine.registers.set_f(self.machine.registers.get_f().normalize());
+            }
+            Opcode::Sio => {
+                if self.check_privileged() {
+                    Processor::not_implemented(&mut self.machine, "SIO");
+                }
+            }
+            Opcode::Hio => {
+                if self.check_privileged() {
+                    Processor::not_implemented(&mut self.machine, "HIO");
+                }
+            }
+            Opcode::Tio => {
+                if self.check_privileged() {
+                    Processor::not_implemented(&mut self.machine, "TIO");
+                }
             }
-            Opcode::Fix => Processor::not_implemented("FIX"),
-            Opcode::Norm => Processor::not_implemented("NORM"),
-            Opcode::Sio => Processor::not_implemented("SIO"),
-            Opcode::Hio => Processor::not_implemented("HIO"),
-            Opcode::Tio => Processor::not_implemented("TIO"),
             _ => return false,
         };
 
@@ -140,71 +660,70 @@ impl Processor {
         };
 
         let (r1, r2) = get_r1_r2(&operand);
-        let r1_val = self.machine.registers.get_reg(r1.try_into().unwrap());
-        let r2_val = self.machine.registers.get_reg(r2.try_into().unwrap());
+        if let Opcode::Svc = opcode {
+            // SVC n: r1 == n
+            self.machine.interrupts.raise(InterruptClass::Svc, r1);
+            return true;
+        }
+
+        // CLEAR and TIXR use only r1, the second field of SHIFTL and SHIFTR is the count - 1
+        let uses_r2 =
+            !matches!(opcode, Opcode::Clear | Opcode::Tixr | Opcode::Shiftl | Opcode::Shiftr);
+        let valid = |r: u8| Registers::is_valid_index(r.into());
+        if !valid(r1) || (uses_r2 && !valid(r2)) {
+            let byte = *opcode as u8;
+            Processor::program_check(&mut self.machine, ProgramCheck::IllegalInstruction(byte));
+            return true;
+        }
+        let r1_val = self.machine.registers.get_reg(r1.into());
+        let r2_val = match uses_r2 {
+            true => self.machine.registers.get_reg(r2.into()),
+            false => 0,
+        };
 
         match opcode {
-            Opcode::Addr => self.machine.registers.set_reg(r2.try_into().unwrap(), r2_val + r1_val),
-            Opcode::Subr => self.machine.registers.set_reg(r2.try_into().unwrap(), r2_val - r1_val),
-            Opcode::Mulr => self.machine.registers.set_reg(r2.try_into().unwrap(), r2_val * r1_val),
-            Opcode::Divr => self.machine.registers.set_reg(r2.try_into().unwrap(), r2_val / r1_val),
-            Opcode::Compr => self.machine.registers.set_sw(match r1_val.cmp(&r2_val) {
-                std::cmp::Ordering::Less => -1,
-                std::cmp::Ordering::Equal => 0,
-                std::cmp::Ordering::Greater => 1,
-            }),
+            Opcode::Addr => self.machine.registers.set_reg(r2.into(), r2_val + r1_val),
+            Opcode::Subr => self.machine.registers.set_reg(r2.into(), r2_val - r1_val),
+            Opcode::Mulr => {
+                self.machine.registers.set_reg(r2.into(), r2_val.wrapping_mul(r1_val));
+            }
+            Opcode::Divr => {
+                if r1_val == 0 {
+                    Processor::program_check(&mut self.machine, ProgramCheck::DivisionByZero);
+                } else {
+                    self.machine.registers.set_reg(r2.into(), r2_val / r1_val);
+                }
+            }
+            Opcode::Compr => self.machine.registers.set_cc(r1_val.cmp(&r2_val)),
             Opcode::Shiftl => {
-                self.machine.registers.set_reg(r1.try_into().unwrap(), r1_val << r2_val);
+                let val = r1_val.wrapping_shl(r2 as u32 + 1);
+                self.machine.registers.set_reg(r1.into(), val);
             }
             Opcode::Shiftr => {
-                self.machine.registers.set_reg(r1.try_into().unwrap(), r1_val >> r2_val);
+                let val = r1_val.wrapping_shr(r2 as u32 + 1);
+                self.machine.registers.set_reg(r1.into(), val);
             }
-            Opcode::Rmo => self.machine.registers.set_reg(r2.try_into().unwrap(), r1_val),
+            Opcode::Rmo => self.machine.registers.set_reg(r2.into(), r1_val),
             Opcode::Clear => self.machine.registers.set_reg(r1.try_into().unwrap(), 0),
             Opcode::Tixr => {
                 self.machine.registers.set_x(self.machine.registers.get_x() + 1);
-                self.machine.registers.set_sw(match self.machine.registers.get_x().cmp(&r1_val) {
-                    std::cmp::Ordering::Less => -1,
-                    std::cmp::Ordering::Equal => 0,
-                    std::cmp::Ordering::Greater => 1,
-                });
+                self.machine.registers.set_cc(self.machine.registers.get_x().cmp(&r1_val));
             }
-            Opcode::Svc => Processor::not_implemented("SVC"),
             _ => return false,
         };
 
         true
     }
     /// opcode: 6b
-    /// ni: 1b,1b == n,i
-    /// \   0, 0 -> SIC
+    /// bits: n,i,x,b,p,e
+    /// \   n, i == 0, 0 -> SIC
     /// \   else -> F3 or F4
-    /// operand: 16b or 24b
-    /// \   SIC  -> 1b,15b == x,addr
-    /// \   F3   -> 1b,1b,1b,1b,12b == x,b,p,e,offset
-    /// \   F4   -> 1b,1b,1b,1b,20b == x,b,p,e,addr
-    fn exec_sic_f3_f4(
-        &mut self,
-        opcode: &Opcode,
-        first_byte: &u8,
-        second_byte: &u8,
-        third_byte: &u8,
-    ) -> bool {
-        let bits = get_format_sic_f3_f4_bits(&first_byte, &second_byte);
-        let addr = {
-            if is_format_sic(&bits) {
-                ((second_byte & 0x7F) as u32) << 8 | *third_byte as u32
-            } else if is_format_f3(&bits) {
-                ((second_byte & 0x0F) as u32) << 8 | *third_byte as u32
-            } else if is_format_f4(&bits) {
-                let fourth_byte = self.fetch();
-                // println!("byte4={:08b}\n", fourth_byte);
-                ((second_byte & 0x0F) as u32) << 16 | (*third_byte as u32) << 8 | fourth_byte as u32
-            } else {
-                panic!("INVALID STATE");
-            }
-        } as usize;
-        // println!("bits={}", bits);
+    /// addr: address field
+    /// \   SIC  -> 15b addr
+    /// \   F3   -> 12b offset
+    /// \   F4   -> 20b addr
+    fn exec_sic_f3_f4(&mut self, opcode: &Opcode, bits: &FormatSicF3F4Bits, addr: usize) -> bool {
+        let bits = *bits;
 
         match opcode {
             // ***** immediate addressing not possible *****
@@ -257,7 +776,14 @@ impl Processor {
                     &mut self.machine,
                 );
             }
-            Opcode::Stf => Processor::not_implemented("STF"),
+            Opcode::Stf => {
+                Processor::store_float(
+                    &bits,
+                    addr,
+                    self.machine.registers.get_f_as_bytes(),
+                    &mut self.machine,
+                );
+            }
             Opcode::Stt => {
                 Processor::store_word(
                     &bits,
@@ -277,19 +803,19 @@ impl Processor {
 
             // jumps
             Opcode::Jeq => {
-                if self.machine.registers.get_sw() == 0 {
+                if self.machine.registers.get_cc() == Ordering::Equal {
                     let address = resolve_address(&bits, addr, &mut self.machine) as i32;
                     self.machine.registers.set_pc(address);
                 }
             }
             Opcode::Jgt => {
-                if self.machine.registers.get_sw() == 1 {
+                if self.machine.registers.get_cc() == Ordering::Greater {
                     let address = resolve_address(&bits, addr, &mut self.machine) as i32;
                     self.machine.registers.set_pc(address);
                 }
             }
             Opcode::Jlt => {
-                if self.machine.registers.get_sw() == -1 {
+                if self.machine.registers.get_cc() == Ordering::Less {
                     let address = resolve_address(&bits, addr, &mut self.machine) as i32;
                     self.machine.registers.set_pc(address);
                 }
@@ -335,7 +861,10 @@ impl Processor {
                 let word = Processor::load_word(&bits, addr, &mut self.machine);
                 self.machine.registers.set_s_as_bytes(word);
             }
-            Opcode::Ldf => Processor::not_implemented("LDF"),
+            Opcode::Ldf => {
+                let float = Processor::load_float(&bits, addr, &mut self.machine);
+                self.machine.registers.set_f(float);
+            }
             Opcode::Ldt => {
                 let word = Processor::load_word(&bits, addr, &mut self.machine);
                 self.machine.registers.set_t_as_bytes(word);
@@ -353,11 +882,15 @@ impl Processor {
             }
             Opcode::Mul => {
                 let word = u8arr_to_i24(Processor::load_word(&bits, addr, &mut self.machine));
-                self.machine.registers.set_a(self.machine.registers.get_a() * word);
+                self.machine.registers.set_a(self.machine.registers.get_a().wrapping_mul(word));
             }
             Opcode::Div => {
                 let word = u8arr_to_i24(Processor::load_word(&bits, addr, &mut self.machine));
-                self.machine.registers.set_a(self.machine.registers.get_a() / word);
+                if word == 0 {
+                    Processor::program_check(&mut self.machine, ProgramCheck::DivisionByZero);
+                } else {
+                    self.machine.registers.set_a(self.machine.registers.get_a() / word);
+                }
             }
             Opcode::And => {
                 let word = u8arr_to_i24(Processor::load_word(&bits, addr, &mut self.machine));
@@ -369,48 +902,117 @@ impl Processor {
             }
             Opcode::Comp => {
                 let word = u8arr_to_i24(Processor::load_word(&bits, addr, &mut self.machine));
-                self.machine.registers.set_sw(match self.machine.registers.get_a().cmp(&word) {
-                    std::cmp::Ordering::Less => -1,
-                    std::cmp::Ordering::Equal => 0,
-                    std::cmp::Ordering::Greater => 1,
-                });
+                self.machine.registers.set_cc(self.machine.registers.get_a().cmp(&word));
             }
             Opcode::Tix => {
                 self.machine.registers.set_x(self.machine.registers.get_x() + 1);
                 let word = u8arr_to_i24(Processor::load_word(&bits, addr, &mut self.machine));
-                self.machine.registers.set_sw(match self.machine.registers.get_x().cmp(&word) {
-                    std::cmp::Ordering::Less => -1,
-                    std::cmp::Ordering::Equal => 0,
-                    std::cmp::Ordering::Greater => 1,
-                });
+                self.machine.registers.set_cc(self.machine.registers.get_x().cmp(&word));
             }
 
             // input/output
             Opcode::Rd => {
                 let current_bytes = self.machine.registers.get_a_as_bytes();
                 let address = resolve_address(&bits, addr, &mut self.machine);
+                if !Processor::check_device(address, &mut self.machine) {
+                    return true;
+                }
+                if self.machine.get_device(address).is_waiting() {
+                    self.waiting = true;
+                    return true;
+                }
                 let new_bytes: [u8; 3] =
                     [current_bytes[0], current_bytes[1], self.machine.get_device(address).read()];
                 self.machine.registers.set_a_as_bytes(new_bytes);
             }
             Opcode::Wd => {
                 let address = resolve_address(&bits, addr, &mut self.machine);
+                if !Processor::check_device(address, &mut self.machine) {
+                    return true;
+                }
                 let val_a = self.machine.registers.get_a_as_bytes()[2];
                 self.machine.get_device(address).write(val_a);
             }
-            Opcode::Td => Processor::not_implemented("TD"),
+            Opcode::Td => {
+                // < -> ready, = -> busy
+                let address = resolve_address(&bits, addr, &self.machine);
+                if !Processor::check_device(address, &mut self.machine) {
+                    return true;
+                }
+                let ready = self.machine.get_device(address).test();
+                self.machine.registers.set_cc(if ready { Ordering::Less } else { Ordering::Equal });
+            }
 
             // floating point arithmetic
-            Opcode::Addf => Processor::not_implemented("ADDF"),
-            Opcode::Subf => Processor::not_implemented("SUBF"),
-            Opcode::Mulf => Processor::not_implemented("MULF"),
-            Opcode::Divf => Processor::not_implemented("DIVF"),
-            Opcode::Compf => Processor::not_implemented("COMPF"),
+            Opcode::Addf => {
+                let float = Processor::load_float(&bits, addr, &mut self.machine);
+                match self.machine.registers.get_f().checked_add(&float) {
+                    Some(result) => self.machine.registers.set_f(result),
+                    None => {
+                        Processor::program_check(&mut self.machine, ProgramCheck::FloatOverflow);
+                    }
+                }
+            }
+            Opcode::Subf => {
+                let float = Processor::load_float(&bits, addr, &mut self.machine);
+                match self.machine.registers.get_f().checked_sub(&float) {
+                    Some(result) => self.machine.registers.set_f(result),
+                    None => {
+                        Processor::program_check(&mut self.machine, ProgramCheck::FloatOverflow);
+                    }
+                }
+            }
+            Opcode::Mulf => {
+                let float = Processor::load_float(&bits, addr, &mut self.machine);
+                match self.machine.registers.get_f().checked_mul(&float) {
+                    Some(result) => self.machine.registers.set_f(result),
+                    None => {
+                        Processor::program_check(&mut self.machine, ProgramCheck::FloatOverflow);
+                    }
+                }
+            }
+            Opcode::Divf => {
+                let float = Processor::load_float(&bits, addr, &mut self.machine);
+                if float.is_zero() {
+                    Processor::program_check(&mut self.machine, ProgramCheck::DivisionByZero);
+                    return true;
+                }
+                match self.machine.registers.get_f().checked_div(&float) {
+                    Some(result) => self.machine.registers.set_f(result),
+                    None => {
+                        Processor::program_check(&mut self.machine, ProgramCheck::FloatOverflow);
+                    }
+                }
+            }
+            Opcode::Compf => {
+                let float = Processor::load_float(&bits, addr, &mut self.machine);
+                self.machine.registers.set_cc(self.machine.registers.get_f().compare(&float));
+            }
 
             // others
-            Opcode::Lps => Processor::not_implemented("LPS"),
-            Opcode::Sti => Processor::not_implemented("STI"),
-            Opcode::Ssk => Processor::not_implemented("SSK"),
+            Opcode::Lps => {
+                if self.check_privileged() {
+                    let address = resolve_address(&bits, addr, &self.machine);
+                    if Processor::check_address(address, STATUS_LEN, &mut self.machine) {
+                        self.machine.load_status(address);
+                    }
+                }
+            }
+            Opcode::Sti => {
+                if self.check_privileged() {
+                    let word = u8arr_to_i24(Processor::load_word(&bits, addr, &mut self.machine));
+                    self.machine.interrupts.set_interval_timer(word);
+                }
+            }
+            Opcode::Ssk => {
+                if self.check_privileged() {
+                    let address = resolve_address(&bits, addr, &self.machine);
+                    if Processor::check_address(address, 1, &mut self.machine) {
+                        let key = (self.machine.registers.get_a() & 0x0F) as u8;
+                        self.machine.memory.set_key(address, key);
+                    }
+                }
+            }
             _ => return false,
         };
 
@@ -418,6 +1020,45 @@ impl Processor {
     }
 
     // helpers
+    /// privileged instructions raise a program interrupt in user mode
+    fn check_privileged(&mut self) -> bool {
+        if self.machine.registers.is_supervisor() {
+            return true;
+        }
+        Processor::program_check(&mut self.machine, ProgramCheck::PrivilegedInstruction);
+        false
+    }
+
+    /// raises a program interrupt if the address is out of range or user mode may not write there
+    fn check_writable(address: usize, len: usize, machine: &mut Machine) -> bool {
+        if !Processor::check_address(address, len, machine) {
+            return false;
+        }
+        if machine.can_write(address, len) {
+            return true;
+        }
+        Processor::program_check(machine, ProgramCheck::MemoryProtection(address));
+        false
+    }
+
+    /// raises a program interrupt if the address is out of range
+    fn check_address(address: usize, len: usize, machine: &mut Machine) -> bool {
+        if machine.memory.is_valid(address, len) {
+            return true;
+        }
+        Processor::program_check(machine, ProgramCheck::AddressOutOfRange(address));
+        false
+    }
+
+    /// raises a program interrupt if there is no such device
+    fn check_device(device: usize, machine: &mut Machine) -> bool {
+        if machine.has_device(device) {
+            return true;
+        }
+        Processor::program_check(machine, ProgramCheck::InvalidDevice(device));
+        false
+    }
+
     fn store_word(
         bits: &FormatSicF3F4Bits,
         mut address: usize,
@@ -429,7 +1070,10 @@ impl Processor {
         //     "DOING STORE WORD at {} with word [{}, {}, {}]",
         //     address, word[0], word[1], word[2]
         // );
-        machine.memory.set_word(address, word);
+        if Processor::check_writable(address, 3, machine) {
+            machine.memory.note_write(address, &word);
+            machine.memory.set_word(address, word);
+        }
     }
 
     fn store_byte(
@@ -440,16 +1084,32 @@ impl Processor {
     ) -> () {
         address = resolve_address(bits, address, machine);
         // println!("DOING STORE BYTE at {} with word {}", address, byte);
-        machine.memory.set_byte(address, byte);
+        if Processor::check_writable(address, 1, machine) {
+            machine.memory.note_write(address, &[byte]);
+            machine.memory.set_byte(address, byte);
+        }
+    }
+
+    fn store_float(
+        bits: &FormatSicF3F4Bits,
+        mut address: usize,
+        float: [u8; 6],
+        machine: &mut Machine,
+    ) {
+        address = resolve_address(bits, address, machine);
+        if Processor::check_writable(address, 6, machine) {
+            machine.memory.note_write(address, &float);
+            machine.memory.set_float(address, float);
+        }
     }
 
     fn load_word(bits: &FormatSicF3F4Bits, mut address: usize, machine: &mut Machine) -> [u8; 3] {
         if is_immediate(bits) {
             if is_pc_relative(bits) {
-                address += machine.registers.get_pc() as usize;
+                address = address.wrapping_add(machine.registers.get_pc() as usize);
             }
             if is_base_relative(bits) {
-                address += machine.registers.get_b() as usize;
+                address = address.wrapping_add(machine.registers.get_b() as usize);
             }
             return i24_to_u8arr(address as i32);
         }
@@ -457,6 +1117,10 @@ impl Processor {
         // println!("OG address={}", address);
         address = resolve_address(bits, address, machine);
         // println!("resolved address={}", address);
+        if !Processor::check_address(address, 3, machine) {
+            return [0; 3];
+        }
+        machine.memory.note_read(address, 3);
         let word = machine.memory.get_word(address);
         // println!("word={:2x},{:2x},{:2x}", word[0], word[1], word[2]);
         word
@@ -465,97 +1129,86 @@ impl Processor {
     fn load_byte(bits: &FormatSicF3F4Bits, mut address: usize, machine: &mut Machine) -> u8 {
         if is_immediate(bits) {
             if is_pc_relative(bits) {
-                address += machine.registers.get_pc() as usize;
+                address = address.wrapping_add(machine.registers.get_pc() as usize);
             }
             if is_base_relative(bits) {
-                address += machine.registers.get_b() as usize;
+                address = address.wrapping_add(machine.registers.get_b() as usize);
             }
             return (address & 0xFF) as u8;
         }
 
         address = resolve_address(bits, address, machine);
+        if !Processor::check_address(address, 1, machine) {
+            return 0;
+        }
+        machine.memory.note_read(address, 1);
         machine.memory.get_byte(address)
     }
 
+    /// immediate operand is converted like FLOAT would
+    fn load_float(bits: &FormatSicF3F4Bits, mut address: usize, machine: &mut Machine) -> SicFloat {
+        if is_immediate(bits) {
+            if is_pc_relative(bits) {
+                address = address.wrapping_add(machine.registers.get_pc() as usize);
+            }
+            if is_base_relative(bits) {
+                address = address.wrapping_add(machine.registers.get_b() as usize);
+            }
+            return SicFloat::from_i24(address as i32);
+        }
+
+        address = resolve_address(bits, address, machine);
+        if !Processor::check_address(address, 6, machine) {
+            return SicFloat::zero();
+        }
+        machine.memory.note_read(address, 6);
+        SicFloat::from_bytes(machine.memory.get_float(address))
+    }
+
     // errors
-    fn not_implemented(mnemonic: &str) -> () {
-        panic!("{mnemonic}: NOT IMPLMENTED!");
+    fn not_implemented(machine: &mut Machine, mnemonic: &'static str) {
+        Processor::program_check(machine, ProgramCheck::NotImplemented(mnemonic));
     }
-    fn invalid_opcode(invalid_opcode_byte: u8) -> () {
-        panic!("{invalid_opcode_byte}: NOT VALID OPCODE!");
+    fn program_check(machine: &mut Machine, cause: ProgramCheck) {
+        machine.interrupts.raise_program_check(cause);
     }
 
-    // Dissasemble and return (len in bytes, instruction)
+    /// Disassemble the instruction at addr, with its label, the label of its target and the
+    /// effective address of a memory operand (see disassembler::decode).
+    /// return: (len in bytes, `0x000000: 4B 10 00 33 rec      +JSUB   sinit        =000033`)
     pub fn disassemble_at(&self, addr: usize) -> (usize, String) {
         let mem = &self.machine.memory;
-
-        let b1 = mem.get_byte(addr);
-        let maybe_opcode = Opcode::from_byte(b1 & 0xFC);
-
-        // raw byte on non opcode
-        let opcode = match maybe_opcode {
-            None => {
-                let s = format!("{addr:06X}: {:02X}     ???", b1);
-                return (1, s);
-            }
-            Some(op) => op,
-        };
-
-        // Format 1
-        if matches!(
-            opcode,
-            Opcode::Float | Opcode::Fix | Opcode::Norm | Opcode::Sio | Opcode::Hio | Opcode::Tio
-        ) {
-            let mnemonic = format!("{:?}", opcode);
-            let s = format!("0x{addr:06X}: {:02X}      {mnemonic}", b1);
-            return (1, s);
+        if !mem.is_valid(addr, 1) {
+            return (1, format!("0x{addr:06X}: --"));
         }
-
-        // Format 2
-        let b2 = mem.get_byte(addr + 1);
-        if matches!(
-            opcode,
-            Opcode::Addr
-                | Opcode::Subr
-                | Opcode::Mulr
-                | Opcode::Divr
-                | Opcode::Compr
-                | Opcode::Shiftl
-                | Opcode::Shiftr
-                | Opcode::Rmo
-                | Opcode::Clear
-                | Opcode::Tixr
-                | Opcode::Svc
-        ) {
-            let mnemonic = format!("{:?}", opcode);
-            let s = format!("0x{addr:06X}: {:02X} {:02X}    {mnemonic}", b1, b2);
-            return (2, s);
-        }
-
-        // SIC / F3 / F4
-        let bits = get_format_sic_f3_f4_bits(&b1, &b2);
-        let len = if is_format_sic(&bits) {
-            3
-        } else if is_format_f3(&bits) {
-            3
-        } else if is_format_f4(&bits) {
-            4
-        } else {
-            panic!("Invalid state");
+        let symbol_at = |address: usize| {
+            Some(self.symbol_at(address).map_or_else(|| format!("{address:06X}"), str::to_string))
         };
+        let instruction = disassembler::decode(&self.machine, addr, &symbol_at);
+        let len = instruction.len;
+        let bytes: Vec<String> = (0..len)
+            .filter(|i| mem.is_valid(addr + i, 1))
+            .map(|i| format!("{:02X}", mem.get_byte(addr + i)))
+            .collect();
+        let bytes = bytes.join(" ");
+        let label = self.symbol_at(addr).unwrap_or("");
+        let (mnemonic, operand) = match instruction.opcode {
+            Some(_) => (instruction.mnemonic, instruction.operand),
+            None => ("???".to_string(), String::new()),
+        };
+        let effective =
+            instruction.effective.map_or(String::new(), |address| format!("={address:06X}"));
+        let line =
+            format!("0x{addr:06X}: {bytes:<11} {label:8} {mnemonic:7} {operand:12} {effective}");
+        (len, line.trim_end().to_string())
+    }
 
-        let mut bytes = Vec::with_capacity(len);
-        for i in 0..len {
-            bytes.push(mem.get_byte(addr + i));
-        }
-
-        let mnemonic = format!("{:?}", opcode);
-
-        let byte_str = bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
-
-        let s = format!("0x{addr:06X}: {:<6} {mnemonic}", byte_str);
-
-        (len, s)
+    /// Memory from start to end as assembler source (see disassembler::to_source), named after
+    /// the label at start.
+    pub fn source(&self, start: usize, end: usize) -> String {
+        let name = self.symbol_at(start).unwrap_or("PROG");
+        let symbol_at = |address: usize| self.symbol_at(address).map(str::to_string);
+        disassembler::to_source(&self.machine, name, start, end, None, &symbol_at)
     }
 }
 
@@ -570,27 +1223,42 @@ pub trait ProcessorExt {
     fn get_speed(&self) -> i64;
     fn set_speed(&self, hz: i64);
 
-    fn load_file(&self, file_name: &str);
+    /// link the files' control sections one after another from address
+    /// address: None -> where the first one was assembled
+    fn load_files(&self, file_names: &[&str], address: Option<usize>) -> Result<(), String>;
+
+    /// Add the labels of a .lst listing or symbol file (see symbols).
+    /// address: where the first of them is now, None -> where it was assembled
+    /// return: number of symbols added
+    fn load_symbols(&self, file_name: &str, address: Option<usize>) -> Result<usize, String>;
+
+    fn save_snapshot(&self, file_name: &str) -> Result<(), String>;
+    /// write memory from start to end as assembler source
+    fn save_source(&self, file_name: &str, start: usize, end: usize) -> Result<(), String>;
+    /// return: see Processor::restore
+    fn restore_snapshot(&self, file_name: &str) -> Result<Vec<String>, String>;
 }
 
 impl ProcessorExt for ProcessorHandle {
     fn start(&self) -> () {
         let mut self_ = self.lock().unwrap();
 
-        let interval = chrono::TimeDelta::nanoseconds(MAX_HZ / self_.speed);
-
-        let guard = {
-            // create new Arc smart pointer to be used by the timer thread
-            let ptr: Arc<Mutex<Processor>> = Arc::clone(&self);
-            self_.timer.schedule_repeating(interval, move || {
-                let mut self__ = ptr.lock().unwrap();
-                self__.execute_instruction();
-            })
-        };
+        // resuming on a breakpoint executes it instead of stopping right away
+        self_.hit_breakpoint = None;
+        self_.hit_watchpoint = None;
+        self_.condition_error = None;
+        if self_.is_breakpoint((self_.machine.registers.get_pc() & MASK_WORD) as usize) {
+            self_.execute_instruction();
+        }
 
-        self_.guard = Some(guard);
+        self_.running = true;
+        self_.run_id += 1;
+        let run_id = self_.run_id;
+        // create new Arc smart pointer to be used by the run loop thread
+        let ptr: Arc<Mutex<Processor>> = Arc::clone(self);
+        thread::spawn(move || run_loop(ptr, run_id));
     }
-    fn stop(&self) -> () { self.lock().unwrap().guard = None; }
+    fn stop(&self) { self.lock().unwrap().running = false; }
     fn step(&self) -> () {
         let mut self_ = self.lock().unwrap();
         self_.execute_instruction();
@@ -599,48 +1267,386 @@ impl ProcessorExt for ProcessorHandle {
     fn get_speed(&self) -> i64 { self.lock().unwrap().speed }
     fn set_speed(&self, hz: i64) -> () { self.lock().unwrap().speed = hz.max(1).min(MAX_HZ); }
 
-    fn load_file(&self, file_name: &str) -> () {
+    fn load_files(&self, file_names: &[&str], address: Option<usize>) -> Result<(), String> {
+        let mut programs = Vec::new();
+        // (file, its first section)
+        let mut files = Vec::new();
+        for file_name in file_names {
+            let sections = ObjectProgram::read(file_name)?;
+            files.push((*file_name, sections[0].name.clone(), sections[0].start));
+            programs.extend(sections);
+        }
+
         let mut processor = self.lock().unwrap();
+        let linked = loader::link(&mut processor.machine, &programs, address)?;
+        // loading isn't journaled
+        processor.clear_history();
+        processor.call_stack.clear();
+        processor.fault = None;
+        processor.halted = None;
 
-        let file = OpenOptions::new()
-            .write(true)
-            .read(true)
-            .create(true)
-            .open(file_name)
-            .expect("Could not open file");
-
-        // load program at ./prog.obj
-        let mut current_load_address: usize;
-        let mut execution_address: i32 = 0;
-        for line in io::BufReader::new(file).lines() {
-            let line = line.unwrap();
-            match line.chars().nth(0).unwrap() {
-                'T' => {
-                    // set load address
-                    current_load_address =
-                        usize::from_str_radix(line.get(1..7).unwrap(), 16).unwrap();
-
-                    // write bytes into memory
-                    let number_of_bytes = i32::from_str_radix(line.get(7..9).unwrap(), 16).unwrap();
-                    for i in 0..number_of_bytes {
-                        let low_ix: usize = (9 + 2 * i) as usize;
-                        let high_ix: usize = (9 + 2 * i + 2) as usize;
-                        let val: u8 =
-                            u8::from_str_radix(line.get(low_ix..high_ix).unwrap(), 16).unwrap();
-
-                        processor.machine.memory.set_byte(current_load_address, val);
-                        current_load_address += 1;
+        // section names and external symbols label their load addresses
+        for (name, address) in &linked.symbols {
+            processor.add_symbol(name, *address);
+        }
+        processor.machine.registers.set_pc(linked.entry as i32);
+
+        // labels from the listing next to each file, moved along with its sections,
+        // a missing or broken listing only means there are no labels
+        for (file_name, section, start) in files {
+            let listing = Path::new(file_name).with_extension("lst");
+            let address = linked.symbols.iter().find(|(name, _)| *name == section);
+            let (Some(listing), Some((_, address))) = (listing.to_str(), address) else {
+                continue;
+            };
+            if let Ok(labels) = symbols::read_symbols(listing) {
+                for (name, label_address) in labels {
+                    if let Some(label_address) = (label_address + address).checked_sub(start) {
+                        processor.add_symbol(&name, label_address);
                     }
                 }
-                'E' => {
-                    // set execution address
-                    execution_address = i32::from_str_radix(line.get(1..7).unwrap(), 16).unwrap();
+            }
+        }
+        Ok(())
+    }
+
+    fn load_symbols(&self, file_name: &str, address: Option<usize>) -> Result<usize, String> {
+        let labels = symbols::read_symbols(file_name)?;
+        let first = labels.iter().map(|(_, address)| *address).min().unwrap_or(0);
+        let mut processor = self.lock().unwrap();
+        for (name, label_address) in &labels {
+            processor.add_symbol(name, label_address - first + address.unwrap_or(first));
+        }
+        Ok(labels.len())
+    }
+
+    fn save_snapshot(&self, file_name: &str) -> Result<(), String> {
+        let snapshot = self.lock().unwrap().snapshot();
+        snapshot.write(file_name)
+    }
+    fn save_source(&self, file_name: &str, start: usize, end: usize) -> Result<(), String> {
+        let source = self.lock().unwrap().source(start, end);
+        fs::write(file_name, source)
+            .map_err(|error| format!("Could not write {file_name}: {error}"))
+    }
+    fn restore_snapshot(&self, file_name: &str) -> Result<Vec<String>, String> {
+        let snapshot = Snapshot::read(file_name)?;
+        self.lock().unwrap().restore(&snapshot)
+    }
+}
+
+/// Run loop thread of a start: executes the instructions due at the speed in slices under
+/// one lock, and sleeps between them until the next one is due. The speed is in cycles, so
+/// an instruction of n cycles holds the next one back for n of them. Exits when the processor
+/// stops running or is started again.
+fn run_loop(processor: ProcessorHandle, run_id: u64) {
+    // pacing restarts when the speed changes
+    let mut speed = 0;
+    let mut since = Instant::now();
+    // cycles executed since then
+    let mut executed: u64 = 0;
+    loop {
+        {
+            let mut processor = processor.lock().unwrap();
+            if processor.run_id != run_id {
+                return;
+            }
+            if processor.fault.is_some() || processor.is_halted() {
+                processor.running = false;
+            }
+            if !processor.running {
+                return;
+            }
+            if processor.speed != speed {
+                speed = processor.speed;
+                since = Instant::now();
+                executed = 0;
+            }
+            let due = (since.elapsed().as_nanos() * speed as u128 / MAX_HZ as u128) as u64;
+            for _ in 0..MAX_SLICE {
+                if executed >= due {
+                    break;
+                }
+                let cycles = processor.cycles;
+                processor.run_step();
+                executed += processor.cycles - cycles;
+                if !processor.running {
+                    return;
                 }
-                _ => {}
             }
         }
 
-        // execute program
-        processor.machine.registers.set_pc(execution_address);
+        let next_nanos = (executed + 1) as u128 * MAX_HZ as u128 / speed as u128;
+        let next = since + Duration::from_nanos(next_nanos as u64);
+        match next.checked_duration_since(Instant::now()) {
+            Some(wait) => thread::sleep(wait.min(MAX_SLEEP)),
+            // behind the speed, let the UI take the lock
+            None => thread::yield_now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::watchpoints::Watchpoint;
+
+    /// processor with code at 0, in supervisor mode
+    fn with_code(code: &[u8]) -> Processor {
+        let mut processor = Processor::new();
+        for (address, byte) in code.iter().enumerate() {
+            processor.machine.memory.set_byte(address, *byte);
+        }
+        processor
+    }
+
+    fn fault_cause(processor: &Processor) -> Option<ProgramCheck> {
+        processor.get_fault().map(|fault| fault.cause.clone())
+    }
+
+    #[test]
+    fn lps_out_of_memory_is_an_addressing_check() {
+        // +LPS 0xFFFF0
+        let mut processor = with_code(&[0xD3, 0x1F, 0xFF, 0xF0]);
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), Some(ProgramCheck::AddressOutOfRange(0xFFFF0)));
+    }
+
+    #[test]
+    fn lps_loads_the_status_block() {
+        // +LPS 0x100, block: SW = 0, PC = 0x000200
+        let mut processor = with_code(&[0xD3, 0x10, 0x01, 0x00]);
+        processor.machine.memory.set_word(0x103, [0x00, 0x02, 0x00]);
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), None);
+        assert_eq!(processor.machine.registers.get_pc(), 0x200);
+        assert!(!processor.machine.registers.is_supervisor());
+    }
+
+    #[test]
+    fn shift_count_is_the_second_field_plus_one() {
+        // SHIFTL A,8  SHIFTR S,2
+        let mut processor = with_code(&[0xA4, 0x07, 0xA8, 0x41]);
+        processor.machine.registers.set_a(1);
+        processor.machine.registers.set_s(0x40);
+        processor.execute_instruction();
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), None);
+        assert_eq!(processor.machine.registers.get_a(), 0x100);
+        assert_eq!(processor.machine.registers.get_s(), 0x10);
+    }
+
+    #[test]
+    fn f2_checks_only_the_registers_it_uses() {
+        // TIXR T  CLEAR X with 7 in the unused field
+        let mut processor = with_code(&[0xB8, 0x50, 0xB4, 0x17]);
+        processor.machine.registers.set_t(5);
+        processor.machine.registers.set_x(3);
+        processor.execute_instruction();
+        assert_eq!(processor.machine.registers.get_x(), 4);
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), None);
+        assert_eq!(processor.machine.registers.get_x(), 0);
+
+        // RMO A,F
+        let mut processor = with_code(&[0xAC, 0x06]);
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), Some(ProgramCheck::IllegalInstruction(0xAC)));
+    }
+
+    #[test]
+    fn ssk_out_of_memory_is_an_addressing_check() {
+        // +SSK 0xFFFFF,X with X = 2
+        let mut processor = with_code(&[0xEF, 0x9F, 0xFF, 0xFF]);
+        processor.machine.registers.set_x(2);
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), Some(ProgramCheck::AddressOutOfRange(0x100001)));
+    }
+
+    #[test]
+    fn svc_switches_to_its_work_area() {
+        // SVC 5, new PC of class I at 0x103
+        let mut processor = with_code(&[0xB0, 0x50]);
+        processor.machine.memory.set_word(0x103, [0x00, 0x30, 0x00]);
+        processor.execute_instruction();
+        let registers = &processor.machine.registers;
+        assert_eq!(registers.get_pc(), 0x3000);
+        assert_eq!(registers.get_icode(), 5);
+        // old status at 0x106, PC after the SVC
+        assert_eq!(processor.machine.memory.get_word(0x109), [0x00, 0x00, 0x02]);
+    }
+
+    #[test]
+    fn privileged_instruction_in_user_mode_is_a_program_interrupt() {
+        // STI 0
+        let mut processor = with_code(&[0xD7, 0x00, 0x00]);
+        processor.machine.registers.set_sw(0);
+        processor.machine.memory.set_word(0x133, [0x00, 0x40, 0x00]);
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), None);
+        assert_eq!(processor.machine.registers.get_pc(), 0x4000);
+        assert_eq!(processor.machine.registers.get_icode(), 0x01);
+    }
+
+    #[test]
+    fn faults_stop_before_anything_changes() {
+        // DIV #0
+        let mut processor = with_code(&[0x25, 0x00, 0x00]);
+        processor.machine.registers.set_a(7);
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), Some(ProgramCheck::DivisionByZero));
+        assert_eq!(processor.machine.registers.get_a(), 7);
+        assert_eq!(processor.machine.registers.get_pc(), 0);
+
+        // FF is no opcode
+        let mut processor = with_code(&[0xFF]);
+        processor.execute_instruction();
+        assert_eq!(fault_cause(&processor), Some(ProgramCheck::IllegalInstruction(0xFF)));
+    }
+
+    #[test]
+    fn run_stops_before_a_breakpoint() {
+        // LDA #1  LDA #2
+        let mut processor = with_code(&[0x01, 0x00, 0x01, 0x01, 0x00, 0x02]);
+        processor.add_breakpoint(3, None);
+        processor.run_step();
+        processor.run_step();
+        assert_eq!(processor.get_hit_breakpoint(), Some(3));
+        assert_eq!(processor.machine.registers.get_a(), 1);
+        assert!(processor.remove_breakpoint(3));
+        processor.run_step();
+        assert_eq!(processor.machine.registers.get_a(), 2);
+    }
+
+    #[test]
+    fn conditional_breakpoint_stops_only_when_it_holds() {
+        // LDA #1  J 0
+        let mut processor = with_code(&[0x01, 0x00, 0x01, 0x3F, 0x2F, 0xFA]);
+        processor.add_breakpoint(0, Some(Expression::parse("A == 1").unwrap()));
+        processor.run_step();
+        assert_eq!(processor.get_hit_breakpoint(), None);
+        processor.run_step();
+        processor.run_step();
+        assert_eq!(processor.get_hit_breakpoint(), Some(0));
+    }
+
+    #[test]
+    fn step_back_undoes_registers_and_memory() {
+        // LDA #5  STA 0x100  LDA #7
+        let mut processor = with_code(&[0x01, 0x00, 0x05, 0x0F, 0x01, 0x00, 0x01, 0x00, 0x07]);
+        for _ in 0..3 {
+            processor.execute_instruction();
+        }
+        assert_eq!(processor.machine.memory.get_word(0x100), [0, 0, 5]);
+        assert_eq!(processor.step_back(2), 2);
+        assert_eq!(processor.machine.registers.get_a(), 5);
+        assert_eq!(processor.machine.registers.get_pc(), 3);
+        assert_eq!(processor.machine.memory.get_word(0x100), [0, 0, 0]);
+        assert_eq!(processor.step_back(5), 1);
+        assert_eq!(processor.get_history_len(), 0);
+    }
+
+    #[test]
+    fn reverse_continue_stops_at_a_breakpoint_or_a_watched_write() {
+        let code = [0x01, 0x00, 0x05, 0x0F, 0x01, 0x00, 0x01, 0x00, 0x07];
+        let mut processor = with_code(&code);
+        for _ in 0..3 {
+            processor.execute_instruction();
+        }
+        processor.add_breakpoint(0, None);
+        processor.machine.memory.watchpoints.add(Watchpoint {
+            address: 0x100,
+            len: 3,
+            kind: WatchKind::Write,
+        });
+        // undoes LDA #7 and then the STA, which wrote the watched word
+        assert_eq!(processor.reverse_continue(), 2);
+        assert_eq!(processor.machine.registers.get_pc(), 3);
+        assert_eq!(processor.reverse_continue(), 1);
+        assert_eq!(processor.get_hit_breakpoint(), Some(0));
+    }
+
+    #[test]
+    fn jump_to_itself_halts_unless_an_interrupt_can_come() {
+        // J *
+        let mut processor = with_code(&[0x3F, 0x2F, 0xFD]);
+        assert!(matches!(processor.run(Some(10)), StopReason::Halted));
+        assert_eq!(processor.get_halted(), Some(0));
+
+        let mut processor = with_code(&[0x3F, 0x2F, 0xFD]);
+        processor.machine.interrupts.set_interval_timer(100);
+        assert!(matches!(processor.run(Some(10)), StopReason::StepLimit));
+    }
+
+    #[test]
+    fn halts_at_the_configured_address_or_opcode() {
+        // LDA #1  LDA #2  RSUB
+        let code = [0x01, 0x00, 0x01, 0x01, 0x00, 0x02, 0x4F, 0x00, 0x00];
+        let mut processor = with_code(&code);
+        processor.set_halt_address(Some(3));
+        assert!(matches!(processor.run(None), StopReason::Halted));
+        assert_eq!((processor.get_halted(), processor.machine.registers.get_a()), (Some(3), 1));
+
+        let mut processor = with_code(&code);
+        processor.set_halt_opcode(Some(0x4F));
+        assert!(matches!(processor.run(None), StopReason::Halted));
+        assert_eq!((processor.get_halted(), processor.machine.registers.get_a()), (Some(6), 2));
+    }
+
+    #[test]
+    fn rd_waits_for_a_key() {
+        // RD 0
+        let mut processor = with_code(&[0xDB, 0x00, 0x00]);
+        processor.execute_instruction();
+        assert_eq!(processor.machine.registers.get_pc(), 0);
+        processor.machine.input_queue(0).unwrap().lock().unwrap().push_back(b'x');
+        processor.execute_instruction();
+        assert_eq!(processor.machine.registers.get_pc(), 3);
+        assert_eq!(processor.machine.registers.get_a(), b'x' as i32);
+    }
+
+    #[test]
+    fn names_addresses_after_the_nearest_label() {
+        let mut processor = with_code(&[]);
+        processor.add_symbol("start", 0x10);
+        processor.add_symbol("begin", 0x10);
+        processor.add_symbol("loop", 0x20);
+        assert_eq!(processor.symbol_at(0x10), Some("begin"));
+        assert_eq!(processor.symbol_near(0x10), "begin");
+        assert_eq!(processor.symbol_near(0x23), "loop+3");
+        assert_eq!(processor.symbol_near(0x5), "000005");
+        assert_eq!(processor.parse_location("loop"), Some(0x20));
+        assert_eq!(processor.parse_location("0x1F"), Some(0x1F));
+        assert_eq!(processor.parse_location("31"), Some(31));
+    }
+
+    #[test]
+    fn call_stack_follows_jsub_and_rsub() {
+        // +JSUB 0x10 ... 0x10: RSUB
+        let mut processor = with_code(&[0x4B, 0x10, 0x00, 0x10]);
+        processor.machine.memory.set_bytes(0x10, &[0x4F, 0x00, 0x00]);
+        processor.execute_instruction();
+        let frames = processor.get_call_stack().get_frames();
+        let frame = (frames[0].call_site, frames[0].target, frames[0].return_address);
+        assert_eq!(frame, (0, 0x10, 4));
+        processor.execute_instruction();
+        assert!(processor.get_call_stack().get_frames().is_empty());
+        // stepping back over the RSUB brings the call back
+        processor.step_back(1);
+        assert_eq!(processor.get_call_stack().get_frames().len(), 1);
+    }
+
+    #[test]
+    fn code_that_rewrites_itself_is_decoded_again() {
+        // LDA #5, LDA #7, STCH 2 -> the first instruction becomes LDA #7
+        let mut processor = with_code(&[0x01, 0x00, 0x05, 0x01, 0x00, 0x07, 0x57, 0x00, 0x02]);
+        for _ in 0..3 {
+            processor.execute_instruction();
+        }
+        assert_eq!(processor.machine.memory.get_byte(2), 0x07);
+        processor.machine.registers.set_a(0);
+        processor.machine.registers.set_pc(0);
+        processor.execute_instruction();
+        assert_eq!(processor.machine.registers.get_a(), 7);
     }
 }
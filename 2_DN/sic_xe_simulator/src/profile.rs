@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::machine::opcodes::Opcode;
+
+// Instruction profile, collected while `profile on` or `run --profile <file>`:
+// executions per address and per opcode, and for every JSUB target its calls and cost.
+// Cost is in executed instructions:
+//   inclusive: from the first instruction of the routine through its RSUB, calls included
+//              (counted once for recursive calls, by the outermost one)
+//   exclusive: instructions of the routine itself, JSUB counts to the caller, RSUB to the callee
+// Instructions outside any routine and idle steps aren't in the routine costs.
+
+#[derive(Default)]
+struct Routine {
+    calls: u64,
+    inclusive: u64,
+    exclusive: u64,
+}
+
+/// active call
+struct Frame {
+    target: usize,
+    /// instructions executed before the first one of the routine
+    entry: u64,
+}
+
+pub struct Profile {
+    instructions: u64,
+    /// address -> executions
+    addresses: HashMap<usize, u64>,
+    /// opcode (without n and i) -> executions
+    opcodes: BTreeMap<u8, u64>,
+    /// JSUB target -> cost
+    routines: BTreeMap<usize, Routine>,
+    stack: Vec<Frame>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            instructions: 0,
+            addresses: HashMap::new(),
+            opcodes: BTreeMap::new(),
+            routines: BTreeMap::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn get_instructions(&self) -> u64 { self.instructions }
+
+    /// Count an executed instruction.
+    /// call: Some(target) -> it was a JSUB to target
+    pub fn record(&mut self, pc: usize, opcode: u8, call: Option<usize>) {
+        let opcode = opcode & 0xFC;
+        self.instructions += 1;
+        *self.addresses.entry(pc).or_default() += 1;
+        *self.opcodes.entry(opcode).or_default() += 1;
+        if let Some(frame) = self.stack.last() {
+            self.routines.entry(frame.target).or_default().exclusive += 1;
+        }
+
+        if let Some(target) = call {
+            self.routines.entry(target).or_default().calls += 1;
+            self.stack.push(Frame { target, entry: self.instructions });
+        } else if opcode == Opcode::Rsub as u8
+            && let Some(frame) = self.stack.pop()
+        {
+            let recursive = self.stack.iter().any(|outer| outer.target == frame.target);
+            if !recursive {
+                let cost = self.instructions - frame.entry;
+                self.routines.entry(frame.target).or_default().inclusive += cost;
+            }
+        }
+    }
+
+    /// Lines of the report, the most executed first.
+    /// symbol_at: label of an address
+    /// limit: lines per table, None -> all
+    pub fn report(
+        &self,
+        symbol_at: &dyn Fn(usize) -> Option<String>,
+        limit: Option<usize>,
+    ) -> Vec<String> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let percent = |count: u64| 100.0 * count as f64 / self.instructions.max(1) as f64;
+        let mut lines = vec![format!("{} instructions", self.instructions)];
+
+        lines.push("Hot spots".to_string());
+        let mut addresses: Vec<(&usize, &u64)> = self.addresses.iter().collect();
+        addresses.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (address, count) in addresses.into_iter().take(limit) {
+            let label = symbol_at(*address).unwrap_or_default();
+            lines.push(format!("  {address:06X} {label:10} {count:>9} {:5.1}%", percent(*count)));
+        }
+
+        lines.push("Opcodes".to_string());
+        let mut opcodes: Vec<(&u8, &u64)> = self.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (opcode, count) in opcodes.into_iter().take(limit) {
+            let mnemonic = match Opcode::from_byte(*opcode) {
+                Some(opcode) => format!("{opcode:?}").to_uppercase(),
+                None => format!("{opcode:02X}"),
+            };
+            lines.push(format!("  {mnemonic:17} {count:>9} {:5.1}%", percent(*count)));
+        }
+
+        lines.push(format!("Routines   {:>7} {:>9} {:>9}", "calls", "inclusive", "exclusive"));
+        let mut routines: Vec<(&usize, &Routine)> = self.routines.iter().collect();
+        routines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+        for (target, routine) in routines.into_iter().take(limit) {
+            let Routine { calls, inclusive, exclusive } = routine;
+            let name = symbol_at(*target).unwrap_or_else(|| format!("{target:06X}"));
+            lines.push(format!("  {name:8} {calls:>7} {inclusive:>9} {exclusive:>9}"));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSUB: u8 = Opcode::Jsub as u8 | 3;
+    const RSUB: u8 = Opcode::Rsub as u8 | 3;
+    const LDA: u8 = Opcode::Lda as u8 | 3;
+
+    #[test]
+    fn counts_routine_costs() {
+        let mut profile = Profile::new();
+        // main calls f, f calls g
+        profile.record(0x000, JSUB, Some(0x100));
+        profile.record(0x100, LDA, None);
+        profile.record(0x103, JSUB, Some(0x200));
+        profile.record(0x200, RSUB, None);
+        profile.record(0x106, RSUB, None);
+        profile.record(0x003, LDA, None);
+        assert_eq!(profile.get_instructions(), 6);
+
+        let symbol_at = |address: usize| (address == 0x100).then(|| "f".to_string());
+        let report = profile.report(&symbol_at, Some(1));
+        assert_eq!(report[0], "6 instructions");
+        assert_eq!(report[1], "Hot spots");
+        assert_eq!(report[2], "  000000                    1  16.7%");
+        assert_eq!(report[3], "Opcodes");
+        // ties go to the lower opcode
+        assert_eq!(report[4], "  LDA                       2  33.3%");
+        assert_eq!(report[6], "  f              1         4         3");
+
+        let routines = profile.report(&symbol_at, None);
+        assert_eq!(routines.last().unwrap(), "  000200         1         1         1");
+    }
+
+    #[test]
+    fn recursive_calls_count_once() {
+        let mut profile = Profile::new();
+        profile.record(0x000, JSUB, Some(0x100));
+        profile.record(0x100, JSUB, Some(0x100));
+        profile.record(0x100, RSUB, None);
+        profile.record(0x103, RSUB, None);
+        let f = &profile.routines[&0x100];
+        assert_eq!((f.calls, f.inclusive, f.exclusive), (2, 3, 3));
+    }
+}
@@ -55,6 +55,7 @@ pub fn get_r1_r2(val: &u8) -> (u8, u8) {
 //  BITS helpers
 // **********************************************
 
+#[derive(Clone, Copy)]
 pub struct FormatSicF3F4Bits {
     n: bool,
     i: bool,
@@ -125,15 +126,19 @@ pub fn resolve_address(bits: &FormatSicF3F4Bits, mut address: usize, machine: &M
         address = saddress as usize;
     }
     if is_base_relative(bits) {
-        address += (machine.registers.get_b()) as usize;
+        address = address.wrapping_add((machine.registers.get_b()) as usize);
     }
 
     if is_indirect(bits) {
+        // out of range stays out of range, so the access itself faults
+        if !machine.memory.is_valid(address, 3) {
+            return address;
+        }
         address = u8arr_to_i24(machine.memory.get_word(address)) as usize;
     }
 
     if is_x(bits) {
-        address += machine.registers.get_x() as usize;
+        address = address.wrapping_add(machine.registers.get_x() as usize);
     }
 
     // println!("resolved address={}", address);
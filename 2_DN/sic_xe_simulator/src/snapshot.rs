@@ -0,0 +1,80 @@
+use std::{collections::BTreeMap, fs};
+
+use serde::{Deserialize, Serialize};
+
+use crate::machine::snapshot::MachineState;
+
+// Snapshot file: JSON of a Snapshot, written by `save <file>`, read by `restore <file>`
+// and `run --restore <file>`. Breakpoints, watchpoints and the step back history aren't in it.
+
+/// format version, bumped on incompatible changes
+const VERSION: u32 = 1;
+
+/// Everything needed to continue a program where it was saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// run speed in Hz
+    pub speed: i64,
+    /// instructions executed so far
+    pub steps: u64,
+    /// cycles they took, missing in older snapshots
+    #[serde(default)]
+    pub cycles: u64,
+    /// name -> hex address
+    pub symbols: BTreeMap<String, String>,
+    pub machine: MachineState,
+}
+
+impl Snapshot {
+    pub fn new(
+        speed: i64,
+        steps: u64,
+        cycles: u64,
+        symbols: BTreeMap<String, String>,
+        machine: MachineState,
+    ) -> Self {
+        Self { version: VERSION, speed, steps, cycles, symbols, machine }
+    }
+
+    pub fn read(file_name: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(file_name)
+            .map_err(|error| format!("Could not read {file_name}: {error}"))?;
+        let snapshot: Snapshot =
+            serde_json::from_str(&text).map_err(|error| format!("{file_name}: {error}"))?;
+        if snapshot.version != VERSION {
+            return Err(format!(
+                "{file_name}: snapshot version {} isn't supported (expected {VERSION})",
+                snapshot.version
+            ));
+        }
+        Ok(snapshot)
+    }
+
+    pub fn write(&self, file_name: &str) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|error| error.to_string())?;
+        fs::write(file_name, text).map_err(|error| format!("Could not write {file_name}: {error}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{machine::Machine, temp_file::TempFile};
+
+    #[test]
+    fn writes_and_reads_a_file_of_this_version() {
+        let file = TempFile::new("snapshot.json");
+        let file_name = file.path();
+        let symbols = BTreeMap::from([("loop".to_string(), "000010".to_string())]);
+        let snapshot = Snapshot::new(100, 5, 20, symbols, Machine::new().save_state());
+        snapshot.write(file_name).unwrap();
+        let read = Snapshot::read(file_name).unwrap();
+        assert_eq!((read.speed, read.steps, read.cycles), (100, 5, 20));
+        assert_eq!(read.symbols, snapshot.symbols);
+
+        let text = fs::read_to_string(file_name).unwrap();
+        fs::write(file_name, text.replace("\"version\": 1", "\"version\": 2")).unwrap();
+        assert!(Snapshot::read(file_name).unwrap_err().contains("version 2 isn't supported"));
+    }
+}
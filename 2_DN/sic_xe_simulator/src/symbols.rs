@@ -0,0 +1,81 @@
+use std::fs;
+
+// Symbol sources for the disassembler
+//
+// .lst listing of the assembler, one line per source line:
+//   address(6)  code  [label]  mnemonic  ["operand", ...]
+//   000004  01000a    recloop       LDA     ["#10"]
+// Labels of EQU lines are values, not addresses, and are skipped.
+//
+// symbol file (any other extension), one symbol per line, # starts a comment:
+//   <name> <hex address>
+
+/// (name, address) of every label in the file, addresses as assembled
+pub fn read_symbols(file_name: &str) -> Result<Vec<(String, usize)>, String> {
+    let text = fs::read_to_string(file_name)
+        .map_err(|error| format!("Could not read {file_name}: {error}"))?;
+    let parse = if file_name.ends_with(".lst") { parse_listing_line } else { parse_symbol_line };
+    let mut symbols = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if let Some(symbol) =
+            parse(line).map_err(|error| format!("{file_name}: line {}: {error}", index + 1))?
+        {
+            symbols.push(symbol);
+        }
+    }
+    Ok(symbols)
+}
+
+fn hex_address(text: &str) -> Result<usize, String> {
+    usize::from_str_radix(text, 16).map_err(|_| format!("Invalid address {text}"))
+}
+
+/// None -> line without a label
+fn parse_listing_line(line: &str) -> Result<Option<(String, usize)>, String> {
+    // operands are the only part with quotes and spaces in it
+    let fields = line.split_once('[').map_or(line, |(fields, _)| fields);
+    match fields.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => Ok(None),
+        [address, _code, label, mnemonic] => {
+            let address = hex_address(address)?;
+            Ok((!mnemonic.eq_ignore_ascii_case("EQU")).then(|| (label.to_string(), address)))
+        }
+        [address, _code, _mnemonic] => hex_address(address).map(|_| None),
+        _ => Err("Expected address, code, label and mnemonic".to_string()),
+    }
+}
+
+fn parse_symbol_line(line: &str) -> Result<Option<(String, usize)>, String> {
+    let line = line.split_once('#').map_or(line, |(line, _)| line);
+    match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => Ok(None),
+        [name, address] => Ok(Some((name.to_string(), hex_address(address)?))),
+        _ => Err("Expected <name> <hex address>".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_of_listing_lines() {
+        let label = |line| parse_listing_line(line).unwrap();
+        let line = "000004  01000a    recloop       LDA     [\"#10\"]";
+        assert_eq!(label(line), Some(("recloop".into(), 4)));
+        assert_eq!(label("000007  4f0000    RSUB"), None);
+        assert_eq!(label("00000A            max   EQU     [\"100\"]"), None);
+        assert_eq!(label(""), None);
+        assert!(parse_listing_line("xyz  01  a  LDA").is_err());
+        assert!(parse_listing_line("000000").is_err());
+    }
+
+    #[test]
+    fn symbol_file_lines() {
+        let symbol = |line| parse_symbol_line(line).unwrap();
+        assert_eq!(symbol("loop 00001A  # the loop"), Some(("loop".into(), 0x1A)));
+        assert_eq!(symbol("# comment"), None);
+        assert_eq!(parse_symbol_line("loop"), Err("Expected <name> <hex address>".into()));
+        assert_eq!(parse_symbol_line("loop zz"), Err("Invalid address zz".into()));
+    }
+}
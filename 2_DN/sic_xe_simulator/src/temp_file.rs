@@ -0,0 +1,27 @@
+use std::{env, fs, path::PathBuf, process};
+
+/// File in the temp directory for a test, removed when dropped, also when the test fails.
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// name: unique among the tests, the process id keeps parallel runs apart
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("sic_xe_{}_{name}", process::id()));
+        // left by a run that was killed
+        let _ = fs::remove_file(&path);
+        Self { path }
+    }
+    pub fn with_contents(name: &str, contents: &[u8]) -> Self {
+        let file = Self::new(name);
+        fs::write(&file.path, contents).unwrap();
+        file
+    }
+
+    pub fn path(&self) -> &str { self.path.to_str().unwrap() }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) { let _ = fs::remove_file(&self.path); }
+}
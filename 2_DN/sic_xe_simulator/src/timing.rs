@@ -0,0 +1,251 @@
+use std::{collections::BTreeMap, fs};
+
+use serde::Deserialize;
+
+use crate::{
+    machine::{decode_cache::Decoded, opcodes::Opcode},
+    sic_xe::{is_format_f4, is_format_sic, is_immediate, is_indirect, is_x},
+};
+
+// Cycle costs, TOML (or JSON for a .json file), loaded by `cycles <file>` and
+// `run --cycles <file>`. Every key is optional, these are the defaults:
+//
+// format1 = 1         # fetching and decoding an instruction, by format
+// format2 = 2
+// format3 = 3
+// format4 = 4
+// sic = 3
+// memory = 2          # per word of the operand read or written, a float is 2 words
+// indirect = 2        # reading the operand address of @m
+// indexed = 1         # adding X to the address
+// device = 10         # RD, WD and TD, instead of a memory access
+// idle = 1            # a step waiting for an interrupt
+//
+// [opcodes]           # extra cycles, by mnemonic, the listed ones replace the defaults
+// MUL = 8
+// DIV = 16
+// MULR = 8
+// DIVR = 16
+// ADDF = 4
+// SUBF = 4
+// MULF = 12
+// DIVF = 24
+// COMPF = 2
+// FLOAT = 2
+// FIX = 2
+// NORM = 2
+//
+// An F3/F4 instruction costs its format, indexed and indirect when it uses them, memory for
+// its operand (immediate operands and jump targets aren't read) or device, and its extra
+// cycles. The speed (`f <hz>`) is in cycles per second and the timer device counts cycles.
+
+const DEFAULT_EXTRA: [(Opcode, u64); 12] = [
+    (Opcode::Mul, 8),
+    (Opcode::Div, 16),
+    (Opcode::Mulr, 8),
+    (Opcode::Divr, 16),
+    (Opcode::Addf, 4),
+    (Opcode::Subf, 4),
+    (Opcode::Mulf, 12),
+    (Opcode::Divf, 24),
+    (Opcode::Compf, 2),
+    (Opcode::Float, 2),
+    (Opcode::Fix, 2),
+    (Opcode::Norm, 2),
+];
+
+/// the file, missing keys keep the defaults
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CostFile {
+    format1: Option<u64>,
+    format2: Option<u64>,
+    format3: Option<u64>,
+    format4: Option<u64>,
+    sic: Option<u64>,
+    memory: Option<u64>,
+    indirect: Option<u64>,
+    indexed: Option<u64>,
+    device: Option<u64>,
+    idle: Option<u64>,
+    #[serde(default)]
+    opcodes: BTreeMap<String, u64>,
+}
+
+/// Cycles each instruction takes, see the format above.
+#[derive(Debug, Clone)]
+pub struct CycleCosts {
+    format1: u64,
+    format2: u64,
+    format3: u64,
+    format4: u64,
+    sic: u64,
+    memory: u64,
+    indirect: u64,
+    indexed: u64,
+    device: u64,
+    idle: u64,
+    /// opcode / 4 -> extra cycles
+    extra: [u64; 64],
+}
+
+impl CycleCosts {
+    pub fn new() -> Self {
+        let mut extra = [0; 64];
+        for (opcode, cycles) in DEFAULT_EXTRA {
+            extra[opcode as usize >> 2] = cycles;
+        }
+        Self {
+            format1: 1,
+            format2: 2,
+            format3: 3,
+            format4: 4,
+            sic: 3,
+            memory: 2,
+            indirect: 2,
+            indexed: 1,
+            device: 10,
+            idle: 1,
+            extra,
+        }
+    }
+
+    pub fn read(file_name: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(file_name)
+            .map_err(|error| format!("Could not read {file_name}: {error}"))?;
+        let file: CostFile = if file_name.ends_with(".json") {
+            serde_json::from_str(&text).map_err(|error| format!("{file_name}: {error}"))?
+        } else {
+            toml::from_str(&text).map_err(|error| format!("{file_name}: {error}"))?
+        };
+
+        let mut costs = Self::new();
+        costs.format1 = file.format1.unwrap_or(costs.format1);
+        costs.format2 = file.format2.unwrap_or(costs.format2);
+        costs.format3 = file.format3.unwrap_or(costs.format3);
+        costs.format4 = file.format4.unwrap_or(costs.format4);
+        costs.sic = file.sic.unwrap_or(costs.sic);
+        costs.memory = file.memory.unwrap_or(costs.memory);
+        costs.indirect = file.indirect.unwrap_or(costs.indirect);
+        costs.indexed = file.indexed.unwrap_or(costs.indexed);
+        costs.device = file.device.unwrap_or(costs.device);
+        costs.idle = file.idle.unwrap_or(costs.idle);
+        for (mnemonic, cycles) in &file.opcodes {
+            let opcode = opcode_named(mnemonic)
+                .ok_or_else(|| format!("{file_name}: unknown opcode {mnemonic}"))?;
+            costs.extra[opcode as usize >> 2] = *cycles;
+        }
+        Ok(costs)
+    }
+
+    /// a step waiting for an interrupt
+    pub fn get_idle(&self) -> u64 { self.idle }
+    /// bytes that couldn't be fetched or decoded, like the shortest instruction
+    pub fn get_fetch_fault(&self) -> u64 { self.format1 }
+
+    pub fn cycles(&self, decoded: &Decoded) -> u64 {
+        match decoded {
+            Decoded::F1(opcode) => self.format1 + self.extra(*opcode),
+            Decoded::F2(opcode, _) => self.format2 + self.extra(*opcode),
+            Decoded::SicF3F4(opcode, bits, _) => {
+                let mut cycles = if is_format_sic(bits) {
+                    self.sic
+                } else if is_format_f4(bits) {
+                    self.format4
+                } else {
+                    self.format3
+                };
+                cycles += self.extra(*opcode);
+                if is_x(bits) {
+                    cycles += self.indexed;
+                }
+                if is_indirect(bits) {
+                    cycles += self.indirect;
+                }
+                if matches!(opcode, Opcode::Rd | Opcode::Wd | Opcode::Td) {
+                    cycles += self.device;
+                } else if !is_immediate(bits) {
+                    cycles += self.memory * operand_words(*opcode);
+                }
+                cycles
+            }
+        }
+    }
+
+    fn extra(&self, opcode: Opcode) -> u64 { self.extra[opcode as usize >> 2] }
+}
+
+/// words of memory the operand of an F3/F4 instruction reads or writes
+fn operand_words(opcode: Opcode) -> u64 {
+    use Opcode::*;
+    match opcode {
+        J | Jeq | Jgt | Jlt | Jsub | Rsub | Ssk => 0,
+        Ldf | Stf | Addf | Subf | Mulf | Divf | Compf => 2,
+        // registers and F
+        Lps => 10,
+        _ => 1,
+    }
+}
+
+fn opcode_named(mnemonic: &str) -> Option<Opcode> {
+    (0..=0xFF)
+        .step_by(4)
+        .filter_map(Opcode::from_byte)
+        .find(|opcode| format!("{opcode:?}").eq_ignore_ascii_case(mnemonic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_file::TempFile;
+
+    fn cycles(costs: &CycleCosts, code: &[u8]) -> u64 {
+        let decoded = Decoded::read(|address| code.get(address).copied(), 0).ok().unwrap();
+        costs.cycles(&decoded)
+    }
+
+    #[test]
+    fn default_costs() {
+        let costs = CycleCosts::new();
+        // FIX, ADDR A,X
+        assert_eq!(cycles(&costs, &[0xC4]), 1 + 2);
+        assert_eq!(cycles(&costs, &[0x90, 0x01]), 2);
+        // LDA #3 reads no memory, LDA 3 and SIC LDA 3 read a word
+        assert_eq!(cycles(&costs, &[0x01, 0x00, 0x03]), 3);
+        assert_eq!(cycles(&costs, &[0x03, 0x00, 0x03]), 3 + 2);
+        assert_eq!(cycles(&costs, &[0x00, 0x00, 0x03]), 3 + 2);
+        // LDA @3,X
+        assert_eq!(cycles(&costs, &[0x02, 0x80, 0x03]), 3 + 1 + 2 + 2);
+        // +LDF 3 reads two words
+        assert_eq!(cycles(&costs, &[0x73, 0x10, 0x00, 0x03]), 4 + 2 * 2);
+        // MUL 3, J 3, WD 1
+        assert_eq!(cycles(&costs, &[0x23, 0x00, 0x03]), 3 + 8 + 2);
+        assert_eq!(cycles(&costs, &[0x3F, 0x00, 0x03]), 3);
+        assert_eq!(cycles(&costs, &[0xDF, 0x00, 0x01]), 3 + 10);
+    }
+
+    #[test]
+    fn file_overrides_the_defaults() {
+        let file = TempFile::new("cycles.toml");
+        let file_name = file.path();
+        fs::write(file_name, "format3 = 5\nidle = 7\n[opcodes]\nmul = 1\n").unwrap();
+        let costs = CycleCosts::read(file_name);
+        fs::write(file_name, "[opcodes]\nFOO = 1\n").unwrap();
+        let unknown = CycleCosts::read(file_name);
+
+        let costs = costs.unwrap();
+        assert_eq!(costs.get_idle(), 7);
+        assert_eq!(cycles(&costs, &[0x03, 0x00, 0x03]), 5 + 2);
+        assert_eq!(cycles(&costs, &[0x23, 0x00, 0x03]), 5 + 1 + 2);
+        // not listed, still the default
+        assert_eq!(cycles(&costs, &[0x27, 0x00, 0x03]), 5 + 16 + 2);
+        assert_eq!(unknown.err(), Some(format!("{file_name}: unknown opcode FOO")));
+    }
+
+    #[test]
+    fn opcodes_by_mnemonic() {
+        assert_eq!(opcode_named("ADDF").map(|opcode| opcode as u8), Some(0x58));
+        assert_eq!(opcode_named("ldf").map(|opcode| opcode as u8), Some(0x70));
+        assert!(opcode_named("FOO").is_none());
+    }
+}
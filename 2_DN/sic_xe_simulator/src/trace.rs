@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use serde::Serialize;
+
+use crate::{machine::Machine, sic_xe::MASK_WORD};
+
+// Execution trace: one record per executed instruction, written by `trace on <file>` and
+// `run --trace <file>`. Numbers are hex, so two runs of a program can be diffed.
+//
+// text (any extension but .jsonl):
+//   <step> <pc> <bytes> <instruction> ea=<effective address> <registers> w <address>=<bytes>...
+//   5 000010 AC40     RMO S,A              ea=------ A=000000 X=000000 L=000003 B=000000 ...
+// JSON lines (.jsonl): a TraceRecord per line
+//   {"step":5,"pc":"000010","bytes":"AC40","instruction":"RMO S,A","ea":null,...}
+//
+// Registers are the ones after the instruction and any interrupt it caused. Idle steps and
+// reads retried while waiting for input aren't recorded.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceRegisters {
+    a: String,
+    x: String,
+    l: String,
+    b: String,
+    s: String,
+    t: String,
+    f: String,
+    pc: String,
+    sw: String,
+}
+
+/// bytes written at address
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceWrite {
+    address: String,
+    bytes: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceRecord {
+    /// number of the instruction since start, from 1
+    step: u64,
+    pc: String,
+    bytes: String,
+    instruction: String,
+    /// effective address of a memory operand
+    ea: Option<String>,
+    registers: TraceRegisters,
+    /// runs of written bytes, with their new values
+    writes: Vec<TraceWrite>,
+}
+
+fn to_hex(bytes: &[u8]) -> String { bytes.iter().map(|byte| format!("{byte:02X}")).collect() }
+
+fn word(value: i32) -> String { format!("{:06X}", value & MASK_WORD) }
+
+impl TraceRecord {
+    /// pc, bytes, instruction, ea: of the instruction, taken before executing it
+    /// machine: after executing it
+    /// journal: (address, old byte) of every byte it wrote
+    pub fn new(
+        step: u64,
+        pc: usize,
+        bytes: &[u8],
+        instruction: String,
+        ea: Option<usize>,
+        machine: &Machine,
+        journal: &[(usize, u8)],
+    ) -> Self {
+        let registers = &machine.registers;
+        let registers = TraceRegisters {
+            a: word(registers.get_a()),
+            x: word(registers.get_x()),
+            l: word(registers.get_l()),
+            b: word(registers.get_b()),
+            s: word(registers.get_s()),
+            t: word(registers.get_t()),
+            f: to_hex(&registers.get_f_as_bytes()),
+            pc: word(registers.get_pc()),
+            sw: word(registers.get_sw()),
+        };
+
+        // consecutive addresses make one run
+        let addresses: BTreeSet<usize> = journal.iter().map(|(address, _)| *address).collect();
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for address in addresses {
+            match runs.last_mut() {
+                Some((start, len)) if *start + *len == address => *len += 1,
+                _ => runs.push((address, 1)),
+            }
+        }
+        let writes = runs
+            .into_iter()
+            .map(|(address, len)| TraceWrite {
+                address: format!("{address:06X}"),
+                bytes: to_hex(machine.memory.get_bytes(address, len)),
+            })
+            .collect();
+
+        Self {
+            step,
+            pc: format!("{pc:06X}"),
+            bytes: to_hex(bytes),
+            instruction,
+            ea: ea.map(|ea| format!("{ea:06X}")),
+            registers,
+            writes,
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let registers = &self.registers;
+        let mut line = format!(
+            "{} {} {:8} {:20} ea={} A={} X={} L={} B={} S={} T={} F={} PC={} SW={}",
+            self.step,
+            self.pc,
+            self.bytes,
+            self.instruction,
+            self.ea.as_deref().unwrap_or("------"),
+            registers.a,
+            registers.x,
+            registers.l,
+            registers.b,
+            registers.s,
+            registers.t,
+            registers.f,
+            registers.pc,
+            registers.sw,
+        );
+        if !self.writes.is_empty() {
+            line.push_str(" w");
+            for write in &self.writes {
+                line.push_str(&format!(" {}={}", write.address, write.bytes));
+            }
+        }
+        line
+    }
+}
+
+/// Writes trace records to a file. After an error nothing more is written, finish reports it.
+pub struct Tracer {
+    file_name: String,
+    writer: BufWriter<File>,
+    jsonl: bool,
+    error: Option<String>,
+}
+
+impl Tracer {
+    /// .jsonl -> JSON lines, anything else -> text
+    pub fn create(file_name: &str) -> Result<Self, String> {
+        let file = File::create(file_name)
+            .map_err(|error| format!("Could not create {file_name}: {error}"))?;
+        Ok(Self {
+            file_name: file_name.to_string(),
+            writer: BufWriter::new(file),
+            jsonl: file_name.ends_with(".jsonl"),
+            error: None,
+        })
+    }
+
+    pub fn file_name(&self) -> &str { &self.file_name }
+
+    pub fn write(&mut self, record: &TraceRecord) {
+        if self.error.is_some() {
+            return;
+        }
+        let line = if self.jsonl {
+            serde_json::to_string(record).unwrap_or_else(|error| error.to_string())
+        } else {
+            record.to_text()
+        };
+        if let Err(error) = writeln!(self.writer, "{line}") {
+            self.error = Some(format!("Could not write {}: {error}", self.file_name));
+        }
+    }
+
+    /// flush the file
+    pub fn finish(mut self) -> Result<(), String> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.flush().map_err(|error| format!("Could not write {}: {error}", self.file_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_file::TempFile;
+    use std::fs;
+
+    fn record() -> TraceRecord {
+        let mut machine = Machine::new();
+        machine.registers.set_a(0x41);
+        machine.registers.set_pc(0x13);
+        machine.memory.set_bytes(0x100, &[0x00, 0x00, 0x41]);
+        machine.memory.set_byte(0x200, 0x41);
+        // two runs, in any order, a byte written twice once
+        let journal = [(0x200, 0), (0x101, 0), (0x100, 0), (0x102, 0), (0x200, 0)];
+        let bytes = [0x0F, 0x20, 0xED];
+        TraceRecord::new(3, 0x10, &bytes, "STA 256".into(), Some(0x100), &machine, &journal)
+    }
+
+    #[test]
+    fn text_record() {
+        let line = record().to_text();
+        assert!(line.starts_with("3 000010 0F20ED   STA 256              ea=000100 A=000041 "));
+        assert!(line.contains(" PC=000013 SW=800000 "));
+        assert!(line.ends_with(" w 000100=000041 000200=41"));
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let file = TempFile::new("trace.jsonl");
+        let file_name = file.path();
+        let mut tracer = Tracer::create(file_name).unwrap();
+        tracer.write(&record());
+        tracer.write(&record());
+        tracer.finish().unwrap();
+
+        let text = fs::read_to_string(file_name).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        let json: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(json["step"], 3);
+        assert_eq!(json["ea"], "000100");
+        assert_eq!(json["registers"]["a"], "000041");
+        assert_eq!(json["writes"][1]["address"], "000200");
+    }
+}